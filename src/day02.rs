@@ -1,10 +1,10 @@
-use crate::intcode::Computer;
+use crate::intcode::symbolic::{self, Expr, Symbolic};
+use crate::intcode::{Computer, IsaProfile, Program, ProgramError};
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::num::ParseIntError;
 
 #[aoc_generator(day2)]
-fn one_line_many_numbers(input: &str) -> Result<Vec<i64>, ParseIntError> {
-    input.split(',').map(str::parse).collect()
+fn program(input: &str) -> Result<Program, ProgramError> {
+    input.parse()
 }
 
 fn compute(input: &[i64], i: i64, j: i64) -> i64 {
@@ -12,6 +12,7 @@ fn compute(input: &[i64], i: i64, j: i64) -> i64 {
     modified_input[1] = i;
     modified_input[2] = j;
     let mut computer = Computer::from(modified_input);
+    computer.set_isa_profile(IsaProfile::Day02);
     computer.run();
     computer.mem_first()
 }
@@ -21,12 +22,29 @@ fn solver1(input: &[i64]) -> i64 {
     compute(input, 12, 2)
 }
 
+const DESIRED_OUTPUT: i64 = 19_690_720;
+
+// With the noun and verb as unknowns, one run gives the result as a formula
+// in them, which can be solved for the output we want
 #[aoc(day2, part2)]
 fn solver2(input: &[i64]) -> Option<i64> {
-    let desired_output = 19_690_720;
+    let mut machine = Symbolic::new(input);
+    machine.set(1, Expr::Var(0));
+    machine.set(2, Expr::Var(1));
+    if machine.run().is_err() {
+        return brute_force(input);
+    }
+    // The formula may not be solvable, for example if part of it is opaque
+    symbolic::solve(&machine.memory(0), DESIRED_OUTPUT, &[0..100, 0..100])
+        .map(|values| values[0] * 100 + values[1])
+        .or_else(|| brute_force(input))
+}
+
+// Try every noun and verb, for programs that can not be run symbolically
+fn brute_force(input: &[i64]) -> Option<i64> {
     for i in 0..100 {
         for j in 0..100 {
-            if compute(input, i, j) == desired_output {
+            if compute(input, i, j) == DESIRED_OUTPUT {
                 return Some(i * 100 + j);
             }
         }
@@ -42,4 +60,16 @@ mod tests {
     fn test_run() {
         assert_eq!(compute(&[1, 0, 0, 0, 99], 0, 0), 2);
     }
+
+    #[test]
+    fn test_symbolic() {
+        let input = Program::load(concat!(env!("CARGO_MANIFEST_DIR"), "/input/2019/day2.txt"));
+        let input = input.unwrap();
+        let mut machine = Symbolic::new(&input);
+        machine.set(1, Expr::Var(0));
+        machine.set(2, Expr::Var(1));
+        machine.run().unwrap();
+        assert_eq!(machine.memory(0).eval(&[12, 2]), Some(solver1(&input)));
+        assert_eq!(solver2(&input), brute_force(&input));
+    }
 }
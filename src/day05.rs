@@ -1,26 +1,27 @@
-use crate::intcode::Computer;
+use crate::intcode::{Computer, IsaProfile, Program, ProgramError};
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::num::ParseIntError;
 
 #[aoc_generator(day5)]
-fn one_line_many_numbers(input: &str) -> Result<Vec<i64>, ParseIntError> {
-    input.split(',').map(str::parse).collect()
+fn program(input: &str) -> Result<Program, ProgramError> {
+    input.parse()
+}
+
+// Run the diagnostic program for the given system and return the last
+// value it outputs
+fn diagnostic(input: &[i64], system: i64) -> i64 {
+    let mut computer = Computer::new(input.to_vec(), vec![system]);
+    computer.set_isa_profile(IsaProfile::Day05);
+    *computer.run().last().expect("No output produced")
 }
 
 #[aoc(day5, part1)]
 fn solver1(input: &[i64]) -> i64 {
-    *Computer::new(input.to_vec(), vec![1])
-        .run()
-        .last()
-        .expect("No output produced")
+    diagnostic(input, 1)
 }
 
 #[aoc(day5, part2)]
 fn solver2(input: &[i64]) -> i64 {
-    *Computer::new(input.to_vec(), vec![5])
-        .run()
-        .last()
-        .expect("No output produced")
+    diagnostic(input, 5)
 }
 
 #[cfg(test)]
@@ -1,13 +1,32 @@
 extern crate permutohedron;
 
-use crate::intcode::Computer;
+use crate::intcode::{Computer, NodeId, Pipeline, Program, ProgramError};
 use aoc_runner_derive::{aoc, aoc_generator};
 use permutohedron::heap_recursive;
-use std::num::ParseIntError;
 
 #[aoc_generator(day7)]
-fn one_line_many_numbers(input: &str) -> Result<Vec<i64>, ParseIntError> {
-    input.split(',').map(str::parse).collect()
+fn program(input: &str) -> Result<Program, ProgramError> {
+    input.parse()
+}
+
+// Connect one amplifier for each phase setting in a chain, optionally
+// feeding the last one back into the first. Returns the pipeline and the
+// last amplifier.
+fn amplifiers(program: &[i64], settings: &[i64], feedback: bool) -> (Pipeline, NodeId) {
+    let mut pipeline = Pipeline::new();
+    let amps: Vec<NodeId> = settings
+        .iter()
+        .map(|&setting| pipeline.add_node(Computer::from(program.to_vec()), vec![setting]))
+        .collect();
+    for pair in amps.windows(2) {
+        pipeline.connect(pair[0], pair[1]);
+    }
+    let last = amps[amps.len() - 1];
+    if feedback {
+        pipeline.connect(last, amps[0]);
+    }
+    pipeline.send(amps[0], 0);
+    (pipeline, last)
 }
 
 #[aoc(day7, part1)]
@@ -17,16 +36,11 @@ fn solver1(program: &[i64]) -> i64 {
 
     // heap_recursive is an algorithm that produces permutations of the data
     heap_recursive(&mut possible_settings, |permutation| {
-        let mut last_out = 0;
-        // Chain the amplifiers to each other
-        for &setting in permutation.iter() {
-            last_out = Computer::new(program.to_vec(), vec![last_out, setting])
-                .run_until_output()
-                .unwrap();
-        }
+        let (mut pipeline, last) = amplifiers(program, permutation, false);
+        let thrust = pipeline.run_until_output(last).unwrap();
         // Collect maximum thrust
-        if last_out > max_thrust {
-            max_thrust = last_out;
+        if thrust > max_thrust {
+            max_thrust = thrust;
         }
     });
     max_thrust
@@ -38,25 +52,13 @@ fn solver2(program: &[i64]) -> i64 {
     let mut possible_settings = [5, 6, 7, 8, 9];
 
     heap_recursive(&mut possible_settings, |permutation| {
-        // Set up five amplifiers for the feedback loop
-        let mut amps = Vec::with_capacity(5);
-        for &setting in permutation.iter() {
-            amps.push(Computer::new(program.to_vec(), vec![setting]));
-        }
-
-        // Keep running them in a chain until one of them halts
-        let mut last_out = 0;
-        'outer: loop {
-            for amp in amps.iter_mut() {
-                amp.more_input(last_out);
-                match amp.run_until_output() {
-                    Some(output) => last_out = output,
-                    None => break 'outer,
-                }
-            }
-            if last_out > max_thrust {
-                max_thrust = last_out;
-            }
+        // Keep the feedback loop running until the last amplifier halts;
+        // its last output is the thrust
+        let (mut pipeline, last) = amplifiers(program, permutation, true);
+        pipeline.run_until_halt(last).unwrap();
+        let thrust = *pipeline.outputs(last).last().unwrap();
+        if thrust > max_thrust {
+            max_thrust = thrust;
         }
     });
     max_thrust
@@ -106,5 +108,4 @@ mod tests {
             18216
         );
     }
-
 }
@@ -1,20 +1,19 @@
-use crate::intcode::Computer;
+use crate::intcode::{Computer, Program, ProgramError};
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::num::ParseIntError;
 
 #[aoc_generator(day9)]
-fn one_line_many_numbers(input: &str) -> Result<Vec<i64>, ParseIntError> {
-    input.split(',').map(str::parse).collect()
+fn program(input: &str) -> Result<Program, ProgramError> {
+    input.parse()
 }
 
 #[aoc(day9, part1)]
 fn solver1(program: &[i64]) -> Option<i64> {
-    Computer::from(program.to_vec()).run_until_output_with(|| 1)
+    Computer::new(program.to_vec(), vec![1]).run_until_output()
 }
 
 #[aoc(day9, part2)]
 fn solver2(program: &[i64]) -> Option<i64> {
-    Computer::from(program.to_vec()).run_until_output_with(|| 2)
+    Computer::new(program.to_vec(), vec![2]).run_until_output()
 }
 
 #[cfg(test)]
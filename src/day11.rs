@@ -1,13 +1,12 @@
-use crate::intcode::Computer;
+use crate::intcode::{Computer, Program, ProgramError, StopReason};
 use aoc_runner_derive::{aoc, aoc_generator};
 use std::cmp::max;
 use std::cmp::min;
 use std::collections::HashMap;
-use std::num::ParseIntError;
 
 #[aoc_generator(day11)]
-fn one_line_many_numbers(input: &str) -> Result<Vec<i64>, ParseIntError> {
-    input.split(',').map(str::parse).collect()
+fn program(input: &str) -> Result<Program, ProgramError> {
+    input.parse()
 }
 
 #[aoc(day11, part1)]
@@ -15,11 +14,19 @@ fn solver1(program: &[i64]) -> usize {
     let mut hm = HashMap::<(i64, i64), i64>::new();
     let (mut x, mut y, mut dir) = (0, 0, 0);
     let mut robot = Computer::from(program.to_vec());
-    while let Some(new_color) = robot.run_until_output_with(|| *hm.get(&(x, y)).unwrap_or(&0)) {
+    loop {
+        let new_color = match robot.resume() {
+            StopReason::NeedsInput => {
+                robot.more_input(*hm.get(&(x, y)).unwrap_or(&0));
+                continue;
+            }
+            StopReason::Output(c) => c,
+            _ => break,
+        };
         hm.insert((x, y), new_color);
-        let turn = match robot.run_until_output_with(|| new_color) {
-            Some(t) => t * 2 - 1,
-            None => break,
+        let turn = match robot.resume() {
+            StopReason::Output(t) => t * 2 - 1,
+            _ => break,
         };
         dir = (dir + turn + 4) % 4;
         match dir {
@@ -38,15 +45,23 @@ fn solver2(program: &[i64]) -> String {
     hm.insert((0, 0), 1);
     let (mut x, mut y, mut xmin, mut ymin, mut xmax, mut ymax, mut dir) = (0, 0, 0, 0, 0, 0, 0);
     let mut robot = Computer::from(program.to_vec());
-    while let Some(new_color) = robot.run_until_output_with(|| *hm.get(&(x, y)).unwrap_or(&0)) {
+    loop {
+        let new_color = match robot.resume() {
+            StopReason::NeedsInput => {
+                robot.more_input(*hm.get(&(x, y)).unwrap_or(&0));
+                continue;
+            }
+            StopReason::Output(c) => c,
+            _ => break,
+        };
         xmin = min(x, xmin);
         xmax = max(x, xmax);
         ymin = min(y, ymin);
         ymax = max(y, ymax);
         hm.insert((x, y), new_color);
-        let turn = match robot.run_until_output_with(|| new_color) {
-            Some(t) => t * 2 - 1,
-            None => break,
+        let turn = match robot.resume() {
+            StopReason::Output(t) => t * 2 - 1,
+            _ => break,
         };
         dir = (dir + turn + 4) % 4;
         match dir {
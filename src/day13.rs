@@ -1,12 +1,10 @@
-use crate::intcode::Computer;
+use crate::intcode::{Computer, Program, ProgramError, StopReason};
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::num::ParseIntError;
 
 #[aoc_generator(day13)]
-fn one_line_many_numbers(input: &str) -> Result<Vec<i64>, ParseIntError> {
-    input.split(',').map(str::parse).collect()
+fn program(input: &str) -> Result<Program, ProgramError> {
+    input.parse()
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -54,27 +52,30 @@ fn breakout(program: &[i64]) -> i64 {
     let mut hm = HashMap::<(i64, i64), Tile>::new();
 
     let mut score = 0;
-    let paddle_x = RefCell::new(0i64);
-    let ball_x = RefCell::new(0i64);
+    let mut paddle_x = 0i64;
+    let mut ball_x = 0;
+    let mut triple = Vec::with_capacity(3);
 
-    // Joystick position depends on the ball and paddle
-    let joystick = || (*ball_x.borrow() - *paddle_x.borrow()).signum();
-
-    while let (Some(x), Some(y), Some(t)) = (
-        arcade.run_until_output_with(joystick),
-        arcade.run_until_output_with(joystick),
-        arcade.run_until_output_with(joystick),
-    ) {
-        if x == -1 {
-            score = t;
-        } else {
-            let t = Tile::new(t);
-            match t {
-                Tile::Ball => *ball_x.borrow_mut() = x,
-                Tile::HPaddle => *paddle_x.borrow_mut() = x,
-                _ => {}
+    loop {
+        match arcade.resume() {
+            // Joystick position depends on the ball and paddle
+            StopReason::NeedsInput => arcade.more_input((ball_x - paddle_x).signum()),
+            StopReason::Output(n) => triple.push(n),
+            _ => break,
+        }
+        if let [x, y, t] = triple[..] {
+            triple.clear();
+            if x == -1 {
+                score = t;
+            } else {
+                let t = Tile::new(t);
+                match t {
+                    Tile::Ball => ball_x = x,
+                    Tile::HPaddle => paddle_x = x,
+                    _ => {}
+                }
+                hm.insert((x, y), t);
             }
-            hm.insert((x, y), t);
         }
     }
     score
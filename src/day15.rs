@@ -1,13 +1,11 @@
-use crate::intcode::Computer;
-use crate::intcode_asm::Debugger;
+use crate::intcode::{Computer, Limits, Program, ProgramError, StopReason};
 use aoc_runner_derive::{aoc, aoc_generator};
 use std::collections::HashMap;
 use std::fmt;
-use std::num::ParseIntError;
 
 #[aoc_generator(day15)]
-fn one_line_many_numbers(input: &str) -> Result<Vec<i64>, ParseIntError> {
-    input.split(',').map(str::parse).collect()
+fn program(input: &str) -> Result<Program, ProgramError> {
+    input.parse()
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -91,6 +89,9 @@ impl fmt::Display for Direction {
     }
 }
 
+// Instructions a robot may execute before we give up on it
+const MAX_INSTRUCTIONS: u64 = 1_000_000;
+
 struct Robot {
     c: Computer,
     x: i64,
@@ -99,16 +100,20 @@ struct Robot {
 
 impl Robot {
     fn new(program: &[i64]) -> Self {
-        Self {
-            c: Computer::from(program.to_vec()),
-            x: 0,
-            y: 0,
-        }
+        let mut c = Computer::from(program.to_vec());
+        c.set_limits(Limits {
+            instructions: Some(MAX_INSTRUCTIONS),
+            ..Default::default()
+        });
+        Self { c, x: 0, y: 0 }
     }
 
     fn move_command(&mut self, hm: &mut TileMap, d: Direction) -> Tile {
         self.c.more_input(d.command());
-        let response = Tile::new(self.c.run_until_output().expect("Robot has halted"));
+        let response = match self.c.resume() {
+            StopReason::Output(n) => Tile::new(n),
+            other => panic!("Robot did not answer: {:?}", other),
+        };
         match response {
             Tile::Empty | Tile::Oxygen => {
                 d.modify_mut(&mut self.x, &mut self.y);
@@ -161,7 +166,7 @@ fn find_oxygen(program: &[i64]) -> i64 {
     let mut d = Direction::North;
     let mut steps = 0;
 
-    while steps != 50000 {
+    loop {
         let backtrack = Some(&Tile::Empty) == hm.get(&d.modify(robot.x, robot.y));
         match robot.move_command(&mut hm, d) {
             Tile::Wall => d = d.turn_right(),
@@ -205,18 +210,21 @@ fn flood_step(m: &mut TileMap) {
 
 #[aoc(day15, part2)]
 fn flood_oxygen(program: &[i64]) -> usize {
-    println!("{}", Debugger::from(program.to_vec()).assembly());
-
     let mut robot = Robot::new(program);
     let mut hm = HashMap::<(i64, i64), Tile>::new();
     hm.insert((0, 0), Tile::Empty);
     let mut d = Direction::North;
 
-    for _ in 0..10000 {
+    // Follow the wall until we are back where we started, facing the same
+    // way: then every part of the maze has been seen
+    loop {
         match robot.move_command(&mut hm, d) {
             Tile::Wall => d = d.turn_right(),
             _ => d = d.turn_left(),
         }
+        if (robot.x, robot.y, d) == (0, 0, Direction::North) {
+            break;
+        }
     }
     // Complete map
     // println!("{}", print(&hm, &robot, d));
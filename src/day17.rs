@@ -1,14 +1,12 @@
-use crate::intcode::Computer;
-use crate::intcode_asm::Debugger;
+use crate::intcode::{AsciiComputer, Computer, Program, ProgramError};
 use aoc_runner_derive::{aoc, aoc_generator};
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
-use std::num::ParseIntError;
 
 #[aoc_generator(day17)]
-fn one_line_many_numbers(input: &str) -> Result<Vec<i64>, ParseIntError> {
-    input.split(',').map(str::parse).collect()
+fn program(input: &str) -> Result<Program, ProgramError> {
+    input.parse()
 }
 
 // From day 10...
@@ -177,7 +175,6 @@ impl Display for Instruction {
 
 #[aoc(day17, part2)]
 fn solver2(program: &[i64]) -> i64 {
-    println!("{}", Debugger::from(program.to_vec()).assembly());
     let mut scanner = Computer::from(program.to_vec());
     let ascii_map = scanner.run();
     let (scaffolds, mut pos, mut dir) = dotpoundmap(
@@ -240,16 +237,14 @@ fn solver2(program: &[i64]) -> i64 {
     // Make the actual robot
     let mut robot_program = program.to_vec();
     robot_program[0] = 2;
-    let mut robot = Computer::new(
-        robot_program,
-        input_str.chars().rev().map(|c| c as u8 as i64).collect(),
-    );
-    let output = robot.run();
-    println!(
-        "{}",
-        output.iter().map(|i| *i as u8 as char).collect::<String>()
-    );
-    output[output.len() - 1]
+    let mut robot = AsciiComputer::from(robot_program);
+    for line in input_str.lines() {
+        robot.send_line(line);
+    }
+    println!("{}", robot.drain_text());
+    robot
+        .answer()
+        .expect("Robot did not report the amount of dust")
 }
 
 #[cfg(test)]
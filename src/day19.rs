@@ -1,11 +1,10 @@
-use crate::intcode::Computer;
+use crate::intcode::{Computer, Program, ProgramError};
 use aoc_runner_derive::{aoc, aoc_generator};
 //use std::collections::HashMap;
-use std::num::ParseIntError;
 
 #[aoc_generator(day19)]
-fn one_line_many_numbers(input: &str) -> Result<Vec<i64>, ParseIntError> {
-    input.split(',').map(str::parse).collect()
+fn program(input: &str) -> Result<Program, ProgramError> {
+    input.parse()
 }
 
 fn is_beam(program: &[i64], pos: (i64, i64)) -> bool {
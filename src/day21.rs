@@ -1,10 +1,22 @@
-use crate::intcode::Computer;
+use crate::intcode::{AsciiComputer, Program, ProgramError};
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::num::ParseIntError;
 
 #[aoc_generator(day21)]
-fn one_line_many_numbers(input: &str) -> Result<Vec<i64>, ParseIntError> {
-    input.split(',').map(str::parse).collect()
+fn program(input: &str) -> Result<Program, ProgramError> {
+    input.parse()
+}
+
+// Feed the springscript to the droid. If it falls into space, the output
+// is a picture of its last moments instead of the hull damage.
+fn run_springdroid(program: &[i64], springcode: &str) -> i64 {
+    let mut computer = AsciiComputer::from(program.to_vec());
+    for line in springcode.lines() {
+        computer.send_line(line);
+    }
+    let text = computer.drain_text();
+    computer
+        .answer()
+        .unwrap_or_else(|| panic!("Springdroid fell into space:\n{}", text))
 }
 
 #[aoc(day21, part1)]
@@ -18,12 +30,7 @@ fn solver1(program: &[i64]) -> i64 {
                       OR T J\n\
                       AND D J\n\
                       WALK\n";
-    let mut computer = Computer::new(
-        program.to_vec(),
-        springcode.chars().rev().map(|c| c as u8 as i64).collect(),
-    );
-    let output = computer.run();
-    output[output.len() - 1]
+    run_springdroid(program, springcode)
 }
 
 #[aoc(day21, part2)]
@@ -46,12 +53,7 @@ fn solver2(program: &[i64]) -> i64 {
                       NOT T T\n\
                       AND T J\n\
                       RUN\n";
-    let mut computer = Computer::new(
-        program.to_vec(),
-        springcode.chars().rev().map(|c| c as u8 as i64).collect(),
-    );
-    let output = computer.run();
-    output[output.len() - 1]
+    run_springdroid(program, springcode)
 }
 
 #[cfg(test)]
@@ -1,13 +1,12 @@
 extern crate text_io;
 
-use crate::intcode::Computer;
+use crate::intcode::{AsciiComputer, Program, ProgramError, Snapshot};
 use aoc_runner_derive::{aoc, aoc_generator};
 use std::fmt;
-use std::num::ParseIntError;
 
 #[aoc_generator(day25)]
-fn one_line_many_numbers(input: &str) -> Result<Vec<i64>, ParseIntError> {
-    input.split(',').map(str::parse).collect()
+fn program(input: &str) -> Result<Program, ProgramError> {
+    input.parse()
 }
 
 #[derive(Debug)]
@@ -21,17 +20,30 @@ impl fmt::Display for Never {
 
 #[aoc(day25, part1)]
 fn interactive(program: &[i64]) -> Option<Never> {
-    let mut computer = Computer::from(program.to_vec());
-    let mut buffer = String::new();
+    let mut droid = AsciiComputer::from(program.to_vec());
     loop {
-        let reader = || {
-            if buffer.is_empty() {
-                buffer = read!("{}\n");
-                buffer += "\n";
+        print!("{}", droid.drain_text());
+        if droid.computer().is_halted() {
+            return None;
+        }
+        let line: String = read!("{}\n");
+        // Save and load are handled here, not by the droid
+        if let Some(path) = line.strip_prefix("!save ") {
+            match droid.computer().snapshot().save(path) {
+                Ok(()) => println!("Saved to {}", path),
+                Err(e) => println!("Could not save: {}", e),
             }
-            buffer.remove(0) as u8 as i64
-        };
-        print!("{}", computer.run_until_output_with(reader)? as u8 as char)
+        } else if let Some(path) = line.strip_prefix("!load ") {
+            match Snapshot::load(path) {
+                Ok(s) => {
+                    droid.computer_mut().restore(&s);
+                    println!("Loaded {}", path);
+                }
+                Err(e) => println!("Could not load: {}", e),
+            }
+        } else {
+            droid.send_line(&line);
+        }
     }
 }
 
@@ -0,0 +1,405 @@
+// Interactive debugger for Intcode programs.
+//
+// Usage: intcode-debugger <program file> [input values...]
+// Type `help` at the prompt for the list of commands.
+
+use advent_of_code_2019::intcode::{
+    Access, BreakId, Computer, Hit, IsaProfile, Program, StopReason,
+};
+use advent_of_code_2019::intcode_asm::Debugger;
+use advent_of_code_2019::intcode_cfg::ControlFlowGraph;
+use advent_of_code_2019::intcode_decompile::decompile;
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+const HELP: &str = "\
+step [n]             execute n instructions (default 1), ignoring breakpoints
+continue             run until a breakpoint, input is needed, or halt
+break <pc>           stop before the instruction at pc
+watch <from> [to] [r|w|rw]
+                     stop after an access to memory from..=to (default rw)
+delete <id>          remove a breakpoint or watchpoint
+breaks               list breakpoints and watchpoints
+print <from> [to]    show memory from..=to
+regs                 show program counter and relative base
+input <n>...         add numbers to the input buffer
+text <line>          add a line of ASCII text (plus newline) to the input
+output               show and clear the output produced so far
+disasm [n]           disassemble n instructions (default 10) from the pc
+decompile            show the code as pseudo-code with loops and if/else
+cfg [dot]            show the basic blocks and where each continues, or
+                     the control-flow graph in Graphviz DOT
+record on|off        keep an undo log, so that `back` works
+back [n]             undo n instructions (default 1)
+profile on|off|report
+                     count executions, or show the counts on the listing
+coverage on|off|report
+                     record which code and jump directions were executed
+isa day02|day05|day09
+                     fault on instructions from later puzzles (default day09)
+quit                 leave the debugger";
+
+struct Session {
+    computer: Computer,
+    breaks: Vec<(BreakId, String)>,
+    output: Vec<i64>,
+}
+
+impl Session {
+    // Execute one command line. Returns false when the user wants to quit.
+    fn command(&mut self, line: &str) -> Result<bool, String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(true),
+        };
+        let args: Vec<&str> = words.collect();
+        let number = |idx: usize, default: Option<i64>| match args.get(idx) {
+            Some(arg) => arg
+                .parse::<i64>()
+                .map_err(|_| format!("Not a number: {}", arg)),
+            None => default.ok_or_else(|| "Missing argument, see `help`".to_owned()),
+        };
+        match command {
+            "s" | "step" => {
+                for _ in 0..number(0, Some(1))? {
+                    if self.computer.is_halted() {
+                        break;
+                    }
+                    self.computer.try_step().map_err(|f| f.to_string())?;
+                    // Report a watchpoint now, not on the next continue
+                    if let Some(hit) = self.computer.take_watch_hit() {
+                        println!("{}", describe(hit));
+                        break;
+                    }
+                }
+                self.collect_output();
+                self.show_current();
+            }
+            "c" | "continue" => self.run()?,
+            "b" | "break" => {
+                let procnt = number(0, None)?;
+                let id = self.computer.add_breakpoint(procnt);
+                self.breaks.push((id, format!("break at {}", procnt)));
+                println!("Breakpoint {} at {}", id, procnt);
+            }
+            "w" | "watch" => {
+                let from = number(0, None)?;
+                let (to, access) = match args.get(1).map(|a| a.parse::<i64>()) {
+                    Some(Ok(to)) => (to, args.get(2)),
+                    _ => (from, args.get(1)),
+                };
+                if to < from {
+                    return Err(format!("Empty range {}..={}", from, to));
+                }
+                let access = match access.copied() {
+                    None | Some("rw") => Access::ReadWrite,
+                    Some("r") => Access::Read,
+                    Some("w") => Access::Write,
+                    Some(other) => return Err(format!("Unknown access {}", other)),
+                };
+                let id = self.computer.add_watchpoint(from..to + 1, access);
+                let text = format!("watch {}..={} {:?}", from, to, access);
+                println!("Watchpoint {}: {}", id, text);
+                self.breaks.push((id, text));
+            }
+            "d" | "delete" => {
+                let name = format!("#{}", number(0, None)?);
+                match self
+                    .breaks
+                    .iter()
+                    .position(|(id, _)| id.to_string() == name)
+                {
+                    Some(idx) => {
+                        self.computer.remove_break(self.breaks.remove(idx).0);
+                    }
+                    None => return Err(format!("No breakpoint {}", name)),
+                }
+            }
+            "breaks" => {
+                for (id, text) in &self.breaks {
+                    println!("{:>4} {}", id.to_string(), text);
+                }
+            }
+            "p" | "print" => {
+                let from = number(0, None)?;
+                let to = number(1, Some(from))?;
+                for address in from..=to {
+                    match self.computer.peek(address) {
+                        Some(value) => println!("{:6}: {}", address, value),
+                        None => return Err(format!("No such address {}", address)),
+                    }
+                }
+            }
+            "r" | "regs" => println!(
+                "pc = {}, rb = {}{}",
+                self.computer.procnt(),
+                self.computer.relbase(),
+                if self.computer.is_halted() {
+                    " (halted)"
+                } else {
+                    ""
+                }
+            ),
+            "i" | "input" => {
+                for idx in 0..args.len() {
+                    self.computer.more_input(number(idx, None)?);
+                }
+            }
+            "t" | "text" => {
+                let text = line.trim_start()[command.len()..].trim_start();
+                for c in text.chars().chain(Some('\n')) {
+                    self.computer.more_input(c as u8 as i64);
+                }
+            }
+            "o" | "output" => {
+                self.collect_output();
+                let output: Vec<i64> = self.output.drain(..).collect();
+                if output.is_empty() {
+                    println!("(no output)");
+                } else if output.iter().all(|&c| c == 10 || (32..127).contains(&c)) {
+                    print!(
+                        "{}",
+                        output.iter().map(|&c| c as u8 as char).collect::<String>()
+                    );
+                } else {
+                    println!("{:?}", output);
+                }
+            }
+            "disasm" => {
+                let count = number(0, Some(10))?;
+                print!("{}", self.disassemble(count as usize));
+            }
+            "decompile" => print!("{}", decompile(self.computer.memory())),
+            "cfg" => {
+                let cfg = ControlFlowGraph::new(self.computer.memory());
+                match args.first().copied() {
+                    None => print!("{}", cfg.adjacency()),
+                    Some("dot") => print!("{}", cfg.to_dot()),
+                    _ => return Err("Use `cfg` or `cfg dot`".to_owned()),
+                }
+            }
+            "record" => match args.first().copied() {
+                Some("on") => self.computer.start_recording(),
+                Some("off") => self.computer.stop_recording(),
+                _ => return Err("Use `record on` or `record off`".to_owned()),
+            },
+            "profile" => match args.first().copied() {
+                Some("on") => self.computer.start_profiling(),
+                Some("off") => {
+                    self.computer.stop_profiling();
+                }
+                Some("report") => match self.computer.profile() {
+                    Some(profile) => print!("{}", profile.report(self.computer.memory())),
+                    None => return Err("Not profiling, use `profile on`".to_owned()),
+                },
+                _ => return Err("Use `profile on`, `profile off` or `profile report`".to_owned()),
+            },
+            "coverage" => match args.first().copied() {
+                Some("on") => self.computer.start_coverage(),
+                Some("off") => {
+                    self.computer.stop_coverage();
+                }
+                Some("report") => match self.computer.coverage() {
+                    Some(coverage) => print!("{}", coverage.report(self.computer.memory())),
+                    None => return Err("Not recording coverage, use `coverage on`".to_owned()),
+                },
+                _ => {
+                    return Err("Use `coverage on`, `coverage off` or `coverage report`".to_owned())
+                }
+            },
+            "isa" => {
+                let profile = args
+                    .first()
+                    .ok_or("Use `isa day02`, `isa day05` or `isa day09`")?;
+                self.computer
+                    .set_isa_profile(profile.parse::<IsaProfile>()?);
+            }
+            "back" => {
+                for _ in 0..number(0, Some(1))? {
+                    if !self.computer.step_back() {
+                        println!("No more history");
+                        break;
+                    }
+                }
+                self.show_current();
+            }
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => return Ok(false),
+            _ => return Err(format!("Unknown command {}, see `help`", command)),
+        }
+        Ok(true)
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        loop {
+            match self.computer.try_resume().map_err(|f| f.to_string())? {
+                StopReason::Output(value) => self.output.push(value),
+                StopReason::Halted => {
+                    println!("Halted");
+                    break;
+                }
+                StopReason::NeedsInput => {
+                    println!("Waiting for input");
+                    break;
+                }
+                StopReason::StepLimit => break,
+                StopReason::Limit(limit) => {
+                    println!("{:?} limit reached", limit);
+                    break;
+                }
+                StopReason::Break(hit) => {
+                    println!("{}", describe(hit));
+                    break;
+                }
+            }
+        }
+        self.show_current();
+        Ok(())
+    }
+
+    fn collect_output(&mut self) {
+        self.output.extend(self.computer.output());
+    }
+
+    fn disassemble(&self, count: usize) -> String {
+        let procnt = self.computer.procnt();
+        if procnt < 0 {
+            return String::new();
+        }
+        let isa = self.computer.instruction_set().clone();
+        Debugger::with_instruction_set(self.computer.memory().to_vec(), isa)
+            .disassemble(procnt as usize, count)
+    }
+
+    fn show_current(&self) {
+        print!("{}", self.disassemble(1));
+        if !self.output.is_empty() {
+            println!("({} values of output pending)", self.output.len());
+        }
+    }
+}
+
+fn describe(hit: Hit) -> String {
+    match hit {
+        Hit::Breakpoint { id, .. } | Hit::Condition { id, .. } => format!("Breakpoint {}", id),
+        Hit::Watch {
+            id,
+            procnt,
+            address,
+            access,
+        } => format!(
+            "Watchpoint {}: {:?} of {} by the instruction at {}",
+            id, access, address, procnt
+        ),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <program file> [input values...]", args[0]);
+        process::exit(2);
+    }
+    let program = Program::load(&args[1]).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args[1], e);
+        process::exit(1);
+    });
+    let mut session = Session {
+        computer: Computer::from(program),
+        breaks: Vec::new(),
+        output: Vec::new(),
+    };
+    for value in &args[2..] {
+        if let Err(e) = session.command(&format!("input {}", value)) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+    session.show_current();
+
+    let stdin = io::stdin();
+    loop {
+        print!("(icd) ");
+        io::stdout().flush().expect("Could not write to stdout");
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                break;
+            }
+        }
+        match session.command(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts down from the input, outputting every value
+    fn session() -> Session {
+        let program = vec![3, 100, 4, 100, 1001, 100, -1, 100, 1005, 100, 2, 99];
+        Session {
+            computer: Computer::from(program),
+            breaks: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut session = session();
+        assert_eq!(session.command("\n"), Ok(true));
+        assert_eq!(
+            session.command("input 2 x"),
+            Err("Not a number: x".to_owned())
+        );
+        assert_eq!(
+            session.command("break"),
+            Err("Missing argument, see `help`".to_owned())
+        );
+        assert_eq!(session.command("break 8"), Ok(true));
+        assert_eq!(
+            session.command("delete 7"),
+            Err("No breakpoint #7".to_owned())
+        );
+        assert_eq!(session.command("delete 1"), Ok(true));
+        assert!(session.breaks.is_empty());
+        assert!(session.command("frobnicate").is_err());
+        assert_eq!(session.command("q"), Ok(false));
+    }
+
+    #[test]
+    fn test_watch() {
+        let mut session = session();
+        assert_eq!(
+            session.command("watch 5 2"),
+            Err("Empty range 5..=2".to_owned())
+        );
+        assert_eq!(
+            session.command("watch 5 7 x"),
+            Err("Unknown access x".to_owned())
+        );
+        assert_eq!(session.command("watch 5 r"), Ok(true));
+        assert_eq!(session.command("w 100 101 w"), Ok(true));
+        let texts: Vec<&str> = session.breaks.iter().map(|b| b.1.as_str()).collect();
+        assert_eq!(texts, vec!["watch 5..=5 Read", "watch 100..=101 Write"]);
+
+        // The write by the input instruction stops step, and is not
+        // reported again by continue, which stops at the next write
+        session.command("input 3").unwrap();
+        session.command("step 5").unwrap();
+        assert_eq!(session.computer.procnt(), 2);
+        session.command("continue").unwrap();
+        assert_eq!(session.computer.procnt(), 8);
+        assert_eq!(session.output, vec![3]);
+    }
+}
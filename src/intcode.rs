@@ -1,11 +1,68 @@
-#[derive(Debug, Default)]
-pub struct Computer {
-    memory: Vec<i64>,
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+mod ascii;
+mod breakpoints;
+mod budget;
+mod coverage;
+#[cfg(test)]
+mod difftest;
+mod history;
+mod io;
+mod isa;
+mod memory;
+pub mod network;
+mod pipeline;
+mod profile;
+mod program;
+mod snapshot;
+pub mod symbolic;
+mod threaded;
+mod trace;
+
+pub use ascii::AsciiComputer;
+pub use breakpoints::{Access, BreakId, Hit};
+pub use budget::{Limit, Limits};
+pub use coverage::{Branch, Coverage};
+pub use io::{FnInput, FnOutput, InputSource, IterInput, OutputSink};
+pub(crate) use isa::builtin;
+pub use isa::{Effect, Handler, InstructionSet, IsaError, IsaProfile};
+pub use memory::{Memory, PagedMemory};
+pub use pipeline::{NodeId, Pipeline, PipelineError};
+pub use profile::Profile;
+pub use program::{Program, ProgramError};
+pub use snapshot::{Snapshot, SnapshotError};
+pub use threaded::{ComputerThread, ThreadState};
+pub use trace::{
+    opcode_name, JsonLines, Param, RingBuffer, SharedSink, TextLog, TraceEvent, TraceSink,
+};
+
+// The memory is a Vec<i64> unless another Memory is chosen; see
+// with_memory.
+#[derive(Debug, Default, Clone)]
+pub struct Computer<M: Memory = Vec<i64>> {
+    memory: M,
     procnt: i64,
     relbse: i64,
     halted: bool,
-    input: Vec<i64>,
+    // Values waiting to be read, front first
+    input: VecDeque<i64>,
     output: Vec<i64>,
+    source: io::Port<dyn InputSource + Send>,
+    sink: io::Port<dyn OutputSink + Send>,
+    decoded: Vec<Option<Instruction>>,
+    history: Option<Vec<history::Undo>>,
+    tracer: Option<trace::Tracer>,
+    breaks: Option<Box<breakpoints::Breakpoints<M>>>,
+    // Addresses from here on do not exist
+    limit: Option<i64>,
+    budget: Option<budget::Budget>,
+    profile: Option<Box<profile::Profile>>,
+    coverage: Option<Box<coverage::Coverage>>,
+    // Custom opcodes, if any were registered
+    isa: Option<Arc<isa::InstructionSet>>,
 }
 
 impl From<Vec<i64>> for Computer {
@@ -17,15 +74,39 @@ impl From<Vec<i64>> for Computer {
 
 impl Computer {
     // Create a new Computer using the given memory (the program to run)
-    // and an input vector.
+    // and an input vector. Input is taken from the back of the vector.
     pub fn new(memory: Vec<i64>, input: Vec<i64>) -> Self {
         Self {
             memory,
-            input,
+            input: input.into_iter().rev().collect(),
+            ..Default::default()
+        }
+    }
+
+    // The memory as far as the program has used it. Everything beyond
+    // reads as zero.
+    pub fn memory(&self) -> &[i64] {
+        &self.memory
+    }
+}
+
+impl<M: Memory> Computer<M> {
+    // Create a new Computer with the given memory, which holds the
+    // program to run, and no input.
+    pub fn with_memory(memory: M) -> Self {
+        Self {
+            memory,
             ..Default::default()
         }
     }
 
+    // Make every address from <limit> on a fault when the program uses
+    // it, instead of growing the memory up to it. Without this, the
+    // ADDRESS_LIMIT of the memory applies.
+    pub fn set_address_limit(&mut self, limit: usize) {
+        self.limit = Some(limit as i64);
+    }
+
     // Various modes of running
 
     // Run until halted, no chance to supply more input.
@@ -37,6 +118,15 @@ impl Computer {
         &self.output
     }
 
+    // Like run, but returns a Fault instead of panicking when the program
+    // does something the VM can not execute.
+    pub fn try_run(&mut self) -> Result<&Vec<i64>, Fault> {
+        while !self.halted {
+            self.try_step()?;
+        }
+        Ok(&self.output)
+    }
+
     // Run until there is one output, and return it.
     // Returns None if the program halts.
     pub fn run_until_output(&mut self) -> Option<i64> {
@@ -46,200 +136,590 @@ impl Computer {
         self.output.pop()
     }
 
-    // Run until there is one output, and return it.
-    // Supply a function that is called whenever input is needed.
-    // This clears the internal input buffer.
-    pub fn run_until_output_with<F>(&mut self, mut f: F) -> Option<i64>
-    where
-        F: FnMut() -> i64,
-    {
-        self.input.clear();
-        while (!self.halted) && self.output.is_empty() {
-            if self.opcode() == 3 {
-                self.more_input(f());
-            }
-            self.one_step();
+    // Run until something outside the machine has to happen: it halts,
+    // it wants input that is not in the buffer, or it produces an output.
+    // The output value is taken from the output buffer.
+    pub fn resume(&mut self) -> StopReason {
+        self.resume_for(usize::MAX)
+    }
+
+    // Like resume, but gives up with StepLimit after <max_steps>
+    // instructions have been executed.
+    pub fn resume_for(&mut self, max_steps: usize) -> StopReason {
+        match self.try_resume_for(max_steps) {
+            Ok(reason) => reason,
+            Err(fault) => panic!("{}", fault),
         }
-        self.output.pop()
     }
 
-    // Advance one step.
-    // Supply a function that is called whenever input is needed.
-    // Returns the length of the output.
-    pub fn advance_one_step_with<F>(&mut self, mut f: F) -> usize
-    where
-        F: FnMut() -> i64,
-    {
-        if self.opcode() == 3 && self.input.is_empty() {
-            self.more_input(f());
+    // Like resume, but returns a Fault instead of panicking.
+    pub fn try_resume(&mut self) -> Result<StopReason, Fault> {
+        self.try_resume_for(usize::MAX)
+    }
+
+    // Like resume_for, but returns a Fault instead of panicking.
+    pub fn try_resume_for(&mut self, max_steps: usize) -> Result<StopReason, Fault> {
+        for _ in 0..max_steps {
+            if self.breaks.is_some() {
+                if let Some(hit) = self.check_breaks() {
+                    return Ok(StopReason::Break(hit));
+                }
+            }
+            if self.halted {
+                return Ok(StopReason::Halted);
+            }
+            if self.needs_input() {
+                return Ok(StopReason::NeedsInput);
+            }
+            let outputs = self.output.len();
+            match self.try_step() {
+                Err(Fault {
+                    error: IntcodeError::LimitExceeded(limit),
+                    ..
+                }) => return Ok(StopReason::Limit(limit)),
+                result => result?,
+            }
+            if self.output.len() > outputs {
+                return Ok(StopReason::Output(self.output.pop().unwrap()));
+            }
         }
-        self.one_step();
-        self.output.len()
+        Ok(StopReason::StepLimit)
     }
 
     // Gives the first value in memory. Needed for an early puzzle.
     pub fn mem_first(&self) -> i64 {
-        self.memory[0]
+        self.memory.peek(0)
+    }
+
+    // Inspecting the machine
+
+    pub fn procnt(&self) -> i64 {
+        self.procnt
+    }
+
+    pub fn relbase(&self) -> i64 {
+        self.relbse
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // Value at an address, as the program would read it
+    pub fn peek(&self, address: i64) -> Option<i64> {
+        self.fetch(address).ok()
     }
 
     // Supplies more input to be added to the internal buffer.
     pub fn more_input(&mut self, i: i64) {
-        self.input.insert(0, i)
+        self.input.push_back(i)
     }
 
     pub fn output(&mut self) -> Vec<i64> {
         self.output.drain(..).collect()
     }
 
-    // Process one step starting from current program counter
+    // Process one step starting from current program counter. Faults are
+    // fatal here; use try_step to handle them.
     fn one_step(&mut self) {
-        match self.opcode() {
-            1 => self.bin_op(|a, b| a + b),
-            2 => self.bin_op(|a, b| a * b),
-            3 => self.get_input(),
-            4 => self.give_output(),
-            5 => self.jmp_if(|a| a != 0),
-            6 => self.jmp_if(|a| a == 0),
-            7 => self.bin_op(|a, b| if a < b { 1 } else { 0 }),
-            8 => self.bin_op(|a, b| if a == b { 1 } else { 0 }),
-            9 => self.set_relbase(),
-            99 => self.halted = true,
-            n => panic!("Unknown opcode {}", n),
+        if let Err(fault) = self.try_step() {
+            panic!("{}", fault);
+        }
+    }
+
+    // Process one step starting from current program counter. If the
+    // instruction can not be executed, the machine is left as it was
+    // before the step and the reason is returned with the faulting PC.
+    pub fn try_step(&mut self) -> Result<(), Fault> {
+        let procnt = self.procnt;
+        if self.source.0.is_some() {
+            // Make sure input from the source is in the buffer before the
+            // undo log and trace look at the instruction
+            self.needs_input();
+        }
+        let undo = self.history.is_some().then(|| self.undo_entry());
+        let event = self.tracer.is_some().then(|| self.start_event()).flatten();
+        self.execute().map_err(|error| Fault { procnt, error })?;
+        if self.breaks.is_some() {
+            self.ran_instruction();
+        }
+        if let (Some(history), Some(undo)) = (&mut self.history, undo) {
+            history.push(undo);
+        }
+        if let Some(event) = event {
+            self.finish_event(event);
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self) -> Result<(), IntcodeError> {
+        if self.budget.is_some() {
+            self.check_instructions()?;
+        }
+        let procnt = self.procnt;
+        let ins = self.instruction()?;
+        let result = match ins.opcode {
+            1 => self.bin_op(&ins, i64::checked_add),
+            2 => self.bin_op(&ins, i64::checked_mul),
+            3 => self.get_input(&ins),
+            4 => self.give_output(&ins),
+            5 => self.jmp_if(&ins, |a| a != 0),
+            6 => self.jmp_if(&ins, |a| a == 0),
+            7 => self.bin_op(&ins, |a, b| Some(if a < b { 1 } else { 0 })),
+            8 => self.bin_op(&ins, |a, b| Some(if a == b { 1 } else { 0 })),
+            9 => self.set_relbase(&ins),
+            99 => {
+                self.halted = true;
+                Ok(())
+            }
+            _ => self.custom(&ins),
+        };
+        if result.is_ok() && self.budget.is_some() {
+            self.charge();
         }
+        if result.is_ok() && self.profile.is_some() {
+            self.record(procnt, &ins);
+        }
+        if result.is_ok() && self.coverage.is_some() {
+            self.cover(procnt, &ins);
+        }
+        result
     }
 
     // Information about the current instruction
 
-    // Returns the opcode of the current instruction (removes the mask)
-    fn opcode(&self) -> Opcode {
-        self.memory[self.procnt as usize] % 100
+    // Whether the current instruction is an input instruction that would
+    // find no input, after trying to get some from the source
+    fn needs_input(&mut self) -> bool {
+        self.procnt >= 0
+            && (self.procnt as usize) < self.memory.len()
+            && self.memory.peek(self.procnt as usize) % 100 == 3
+            && !self.fill_input()
     }
 
-    // Returns the mask of the current instruction, in parameter order
-    fn mask(&self) -> Mask {
-        let mut mask = Vec::new();
-        let mut digits = self.memory[self.procnt as usize] / 100;
-        while digits > 0 {
-            mask.push(match digits % 10 {
-                0 => Mode::Position,
-                1 => Mode::Immediate,
-                _ => Mode::Relative,
-            });
-            digits /= 10;
+    // Returns the decoded instruction at the program counter, decoding it
+    // only if it is not in the cache yet
+    fn instruction(&mut self) -> Result<Instruction, IntcodeError> {
+        if self.procnt < 0 || self.procnt as usize >= self.memory.len() {
+            return Err(IntcodeError::PcOutOfBounds);
+        }
+        let pc = self.procnt as usize;
+        if let Some(Some(ins)) = self.decoded.get(pc) {
+            return Ok(*ins);
+        }
+        let ins = Instruction::decode(&self.memory, pc, self.instruction_set())?;
+        if pc < MAX_CACHED {
+            if self.decoded.len() <= pc {
+                self.decoded.resize_with(pc + 1, Default::default);
+            }
+            self.decoded[pc] = Some(ins);
         }
-        Mask(mask)
+        Ok(ins)
     }
 
     // Low-level reading and writing functionality
 
-    // Read one value in the selected mode. Operand is the address or
-    // value to be read.
-    fn read(&self, operand: i64, mode: Mode) -> i64 {
-        match &mode {
-            Mode::Immediate => operand,
-            Mode::Position => *self.memory.get(operand as usize).unwrap_or(&0),
-            Mode::Relative => *self
-                .memory
-                .get((self.relbse + operand) as usize)
-                .unwrap_or(&0),
-        }
-    }
-
-    // Extract the parameters of a function with <amount> parameters
-    fn params(&self, amount: usize) -> Vec<i64> {
-        let mask = self.mask();
-        (0..amount)
-            .map(|i| self.read(self.memory[self.procnt as usize + i + 1], *mask.get(i)))
-            .collect()
-    }
-
-    // Write one value in the specified mode to the address given by the
-    // parameter found <offset> from the current program counter. Immediate
-    // mode can not be used here, and will cause a panic if supplied.
-    fn write(&mut self, offset: usize, value: i64) {
-        // Determine the write address. These are counted from the start in
-        // Position mode, and from the relative base in Relative mode.
-        let mut replacement_pos = match self.mask().get(offset - 1) {
-            Mode::Immediate => panic!(
-                "Attempted to write {} in immediate mode at PC = {}",
-                value, self.procnt
-            ),
-            Mode::Position => 0,
-            Mode::Relative => self.relbse,
+    // Read the raw value at an address. Memory beyond the end of the
+    // program reads as zero; negative addresses do not exist.
+    fn fetch(&self, address: i64) -> Result<i64, IntcodeError> {
+        self.check_address(address)?;
+        Ok(self.memory.peek(address as usize))
+    }
+
+    // Fail for addresses that do not exist: negative ones, and those at
+    // or above the limit if there is one
+    fn check_address(&self, address: i64) -> Result<(), IntcodeError> {
+        if address < 0 {
+            return Err(IntcodeError::NegativeAddress(address));
+        }
+        let default = M::ADDRESS_LIMIT.map(|limit| limit as i64);
+        match self.limit.or(default) {
+            Some(limit) if address >= limit => Err(IntcodeError::AddressTooHigh(address)),
+            _ => Ok(()),
+        }
+    }
+
+    // Read parameter <idx> of the instruction in its own mode.
+    fn read(&self, ins: &Instruction, idx: usize) -> Result<i64, IntcodeError> {
+        let operand = ins.operands[idx];
+        match ins.modes[idx] {
+            Mode::Immediate => Ok(operand),
+            Mode::Position => self.fetch(operand),
+            Mode::Relative => self.fetch(self.relative(operand)?),
+        }
+    }
+
+    // Determine the address that parameter <idx> of the instruction points
+    // to. Addresses are counted from the start in Position mode, and from
+    // the relative base in Relative mode. Immediate mode can not be used
+    // for destinations.
+    fn destination(&self, ins: &Instruction, idx: usize) -> Result<i64, IntcodeError> {
+        let address = match ins.modes[idx] {
+            Mode::Immediate => return Err(IntcodeError::ImmediateWrite),
+            Mode::Position => ins.operands[idx],
+            Mode::Relative => self.relative(ins.operands[idx])?,
         };
-        // Add the value in the 'destination' memory slot
-        replacement_pos += self.memory[self.procnt as usize + offset];
+        self.check_address(address)?;
+        if self.budget.is_some() {
+            self.check_memory(address)?;
+        }
+        Ok(address)
+    }
+
+    // The address of a Relative mode operand
+    fn relative(&self, operand: i64) -> Result<i64, IntcodeError> {
+        self.relbse
+            .checked_add(operand)
+            .ok_or(IntcodeError::Overflow)
+    }
+
+    // Write one value to an address returned by destination(). Decoded
+    // instructions that overlap the address are dropped from the cache.
+    // This is not seen by watchpoints; the VM itself writes with store.
+    fn set(&mut self, address: i64, value: i64) {
+        let address = address as usize;
+        self.memory.poke(address, value);
+        for start in address.saturating_sub(MAX_LENGTH - 1)..=address {
+            if let Some(slot) = self.decoded.get_mut(start) {
+                if matches!(slot, Some(ins) if start + ins.length() > address) {
+                    *slot = None;
+                }
+            }
+        }
+    }
 
-        // Ensure we have enough memory
-        if self.memory.len() <= replacement_pos as usize {
-            self.memory
-                .resize_with(replacement_pos as usize + 1, Default::default);
+    // Write one value to memory on behalf of the running program
+    fn store(&mut self, address: i64, value: i64) {
+        if self.breaks.is_some() {
+            self.watch_access(address, Access::Write);
+        }
+        self.set(address, value);
+    }
+
+    // Read parameter <idx> on behalf of the running program
+    fn load(&mut self, ins: &Instruction, idx: usize) -> Result<i64, IntcodeError> {
+        let value = self.read(ins, idx)?;
+        if self.breaks.is_some() {
+            match ins.modes[idx] {
+                Mode::Immediate => {}
+                Mode::Position => self.watch_access(ins.operands[idx], Access::Read),
+                Mode::Relative => self.watch_access(self.relbse + ins.operands[idx], Access::Read),
+            }
         }
-        self.memory[replacement_pos as usize] = value;
+        Ok(value)
+    }
+
+    // Write one value to the address given by parameter <idx>.
+    fn write(&mut self, ins: &Instruction, idx: usize, value: i64) -> Result<(), IntcodeError> {
+        let address = self.destination(ins, idx)?;
+        self.store(address, value);
+        Ok(())
     }
 
     // Operators supported by the VM
 
     // Standard binary operator. The function supplied is the operation to
-    // be performed.
-    fn bin_op<F>(&mut self, f: F)
+    // be performed; it returns None on overflow.
+    fn bin_op<F>(&mut self, ins: &Instruction, f: F) -> Result<(), IntcodeError>
     where
-        F: Fn(i64, i64) -> i64,
+        F: Fn(i64, i64) -> Option<i64>,
     {
-        let p = self.params(2);
-        self.write(3, f(p[0], p[1]));
+        let value = f(self.load(ins, 0)?, self.load(ins, 1)?).ok_or(IntcodeError::Overflow)?;
+        self.write(ins, 2, value)?;
         self.procnt += 4;
+        Ok(())
     }
 
     // Standard conditional jump with one parameter. The function supplied
     // is used to decide whether to jump.
-    fn jmp_if<F>(&mut self, f: F)
+    fn jmp_if<F>(&mut self, ins: &Instruction, f: F) -> Result<(), IntcodeError>
     where
         F: Fn(i64) -> bool,
     {
-        let p = self.params(2);
-        if f(p[0]) {
-            self.procnt = p[1];
+        let (test, target) = (self.load(ins, 0)?, self.load(ins, 1)?);
+        if f(test) {
+            self.procnt = target;
         } else {
             self.procnt += 3;
         }
+        Ok(())
     }
 
     // Take input from the buffer and put it in the location specified by
     // the only parameter
-    fn get_input(&mut self) {
-        let value = self.input.pop().expect("Input was taken but none is left");
-        self.write(1, value);
+    fn get_input(&mut self, ins: &Instruction) -> Result<(), IntcodeError> {
+        let address = self.destination(ins, 0)?;
+        let value = self.input.pop_front().ok_or(IntcodeError::InputExhausted)?;
+        self.store(address, value);
         self.procnt += 2;
+        Ok(())
     }
 
-    // Add the value of the only parameter to the output buffer
-    fn give_output(&mut self) {
-        let p = self.params(1);
-        self.output.push(p[0]);
+    // Add the value of the only parameter to the output buffer (or sink)
+    fn give_output(&mut self, ins: &Instruction) -> Result<(), IntcodeError> {
+        let value = self.load(ins, 0)?;
+        if self.budget.is_some() {
+            self.check_output()?;
+        }
+        self.emit(value);
+        self.charge_output();
         self.procnt += 2;
+        Ok(())
     }
 
     // Adjust the relative base value by the only parameter
-    fn set_relbase(&mut self) {
-        let p = self.params(1);
-        self.relbse += p[0];
+    fn set_relbase(&mut self, ins: &Instruction) -> Result<(), IntcodeError> {
+        let offset = self.load(ins, 0)?;
+        self.relbse = self.relative(offset)?;
         self.procnt += 2;
+        Ok(())
     }
 }
 
-type Opcode = i64;
-struct Mask(Vec<Mode>);
+// Reasons why resume() handed control back to the caller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Halted,
+    NeedsInput,
+    Output(i64),
+    StepLimit,
+    Break(Hit),
+    // Going on would exceed a limit set with set_limits
+    Limit(Limit),
+}
 
-impl Mask {
-    fn get(&self, idx: usize) -> &Mode {
-        self.0.get(idx).unwrap_or(&Mode::Position)
+// Reasons why the VM can not execute an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntcodeError {
+    UnknownOpcode(i64),
+    ImmediateWrite,
+    InputExhausted,
+    NegativeAddress(i64),
+    // Address at or above the limit set with set_address_limit
+    AddressTooHigh(i64),
+    PcOutOfBounds,
+    // The result of an addition or multiplication, or the relative base,
+    // does not fit in an i64
+    Overflow,
+    // Executing the instruction would exceed a limit set with set_limits
+    LimitExceeded(Limit),
+    // A custom instruction refused to run, for the given reason
+    Custom(&'static str),
+    // The opcode or mode is from a later revision than the profile
+    OpcodeNotInProfile(i64, IsaProfile),
+    ModeNotInProfile(Mode, IsaProfile),
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntcodeError::UnknownOpcode(n) => write!(f, "Unknown opcode {}", n),
+            IntcodeError::ImmediateWrite => write!(f, "Attempted to write in immediate mode"),
+            IntcodeError::InputExhausted => write!(f, "Input was taken but none is left"),
+            IntcodeError::NegativeAddress(a) => write!(f, "Access to negative address {}", a),
+            IntcodeError::AddressTooHigh(a) => write!(f, "Access to address {} above the limit", a),
+            IntcodeError::PcOutOfBounds => write!(f, "Program counter is outside of memory"),
+            IntcodeError::Overflow => write!(f, "Arithmetic overflow"),
+            IntcodeError::LimitExceeded(limit) => write!(f, "{:?} limit reached", limit),
+            IntcodeError::Custom(reason) => write!(f, "{}", reason),
+            IntcodeError::OpcodeNotInProfile(n, profile) => {
+                write!(f, "Opcode {} is not in the {} instruction set", n, profile)
+            }
+            IntcodeError::ModeNotInProfile(mode, profile) => {
+                write!(
+                    f,
+                    "{:?} mode is not in the {} instruction set",
+                    mode, profile
+                )
+            }
+        }
+    }
+}
+
+impl Error for IntcodeError {}
+
+// An IntcodeError together with the program counter of the instruction
+// that caused it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub procnt: i64,
+    pub error: IntcodeError,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at PC = {}", self.error, self.procnt)
     }
 }
 
+impl Error for Fault {}
+
+type Opcode = i64;
+
+// Longest instruction (opcode plus three parameters)
+const MAX_LENGTH: usize = 4;
+
+// Instructions at higher addresses are decoded every time they run, so
+// that a jump far into (paged) memory does not allocate a huge cache
+const MAX_CACHED: usize = 1 << 20;
+
+// An instruction split into its opcode, the mode of each parameter and
+// the raw operands. Unused parameters are Position mode with operand 0.
 #[derive(Debug, Copy, Clone)]
-enum Mode {
+struct Instruction {
+    opcode: Opcode,
+    modes: [Mode; 3],
+    operands: [i64; 3],
+    arity: usize,
+    write: Option<usize>,
+}
+
+impl Instruction {
+    // Decode the instruction starting at <pc>. Fails on opcodes that are
+    // not in the instruction set.
+    fn decode<M: Memory>(
+        memory: &M,
+        pc: usize,
+        isa: &InstructionSet,
+    ) -> Result<Self, IntcodeError> {
+        let word = memory.peek(pc);
+        let opcode = word % 100;
+        let arity = isa.check_opcode(opcode)?;
+        let mut ins = Instruction {
+            opcode,
+            modes: [Mode::Position; 3],
+            operands: [0; 3],
+            arity,
+            write: isa.write_param(opcode),
+        };
+        let mut digits = word / 100;
+        for i in 0..arity {
+            ins.modes[i] = match digits % 10 {
+                0 => Mode::Position,
+                1 => Mode::Immediate,
+                _ => Mode::Relative,
+            };
+            isa.check_mode(ins.modes[i])?;
+            digits /= 10;
+            ins.operands[i] = memory.peek(pc + i + 1);
+        }
+        Ok(ins)
+    }
+
+    // Index of the parameter the instruction writes to, if any
+    fn write_param(&self) -> Option<usize> {
+        self.write
+    }
+
+    // Number of memory cells taken by the instruction
+    fn length(&self) -> usize {
+        self.arity + 1
+    }
+}
+
+// Addressing mode of an instruction parameter
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
     Immediate,
     Position,
     Relative,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fault(program: Vec<i64>, input: Vec<i64>) -> Fault {
+        Computer::new(program, input).try_run().unwrap_err()
+    }
+
+    #[test]
+    fn test_faults() {
+        assert_eq!(
+            fault(vec![1, 0, 0, 0, 42], vec![]),
+            Fault {
+                procnt: 4,
+                error: IntcodeError::UnknownOpcode(42)
+            }
+        );
+        assert_eq!(
+            fault(vec![11101, 1, 1, 0, 99], vec![]).error,
+            IntcodeError::ImmediateWrite
+        );
+        assert_eq!(
+            fault(vec![3, 0, 99], vec![]).error,
+            IntcodeError::InputExhausted
+        );
+        assert_eq!(
+            fault(vec![1, -3, 0, 0, 99], vec![]).error,
+            IntcodeError::NegativeAddress(-3)
+        );
+        assert_eq!(
+            fault(vec![204, -1, 99], vec![]).error,
+            IntcodeError::NegativeAddress(-1)
+        );
+        assert_eq!(
+            fault(vec![1101, i64::MAX, 1, 0, 99], vec![]).error,
+            IntcodeError::Overflow
+        );
+        assert_eq!(
+            fault(vec![1102, 1 << 40, 1 << 40, 0, 99], vec![]).error,
+            IntcodeError::Overflow
+        );
+        assert_eq!(
+            fault(vec![109, i64::MAX, 109, 1, 99], vec![]).error,
+            IntcodeError::Overflow
+        );
+        // Far past the end of a Vec, instead of allocating up to it
+        assert_eq!(
+            fault(vec![1101, 1, 1, 1 << 58, 99], vec![]).error,
+            IntcodeError::AddressTooHigh(1 << 58)
+        );
+        assert_eq!(
+            fault(vec![1105, 1, 100], vec![]),
+            Fault {
+                procnt: 100,
+                error: IntcodeError::PcOutOfBounds
+            }
+        );
+    }
+
+    #[test]
+    fn test_fault_leaves_state() {
+        let mut computer = Computer::from(vec![3, 0, 99]);
+        assert!(computer.try_step().is_err());
+        computer.more_input(7);
+        assert_eq!(computer.try_run(), Ok(&vec![]));
+        assert_eq!(computer.mem_first(), 7);
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // Runs the instruction at 0 twice, incrementing the opcode or an
+        // operand in between
+        let program = |change: i64| {
+            Computer::from(vec![
+                1101, 2, 5, 20, 1001, change, 1, change, 1001, 21, 1, 21, 1008, 21, 2, 22, 1006,
+                22, 0, 99, 0, 0, 0,
+            ])
+        };
+        let mut computer = program(0);
+        computer.run();
+        assert_eq!(computer.memory[20], 10);
+        let mut computer = program(1);
+        computer.run();
+        assert_eq!(computer.memory[20], 8);
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut computer = Computer::from(vec![3, 9, 4, 9, 1105, 1, 0, 99, 99, 0]);
+        assert_eq!(computer.resume(), StopReason::NeedsInput);
+        assert_eq!(computer.resume(), StopReason::NeedsInput);
+        computer.more_input(5);
+        assert_eq!(computer.resume(), StopReason::Output(5));
+        assert_eq!(computer.resume_for(1), StopReason::StepLimit);
+        assert_eq!(computer.resume(), StopReason::NeedsInput);
+        assert_eq!(Computer::from(vec![99]).resume(), StopReason::Halted);
+    }
+}
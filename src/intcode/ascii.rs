@@ -0,0 +1,123 @@
+use super::{Computer, StopReason};
+
+// Wraps a Computer that talks in lines of ASCII text. Output values that
+// are not ASCII are not text, but the numeric answer the program gives;
+// they are kept apart from the text.
+#[derive(Debug, Clone)]
+pub struct AsciiComputer {
+    computer: Computer,
+    // Decoded output that has not been returned yet
+    pending: String,
+    answer: Option<i64>,
+}
+
+impl From<Vec<i64>> for AsciiComputer {
+    fn from(memory: Vec<i64>) -> Self {
+        Self::from(Computer::from(memory))
+    }
+}
+
+impl From<Computer> for AsciiComputer {
+    fn from(computer: Computer) -> Self {
+        Self {
+            computer,
+            pending: String::new(),
+            answer: None,
+        }
+    }
+}
+
+impl AsciiComputer {
+    pub fn computer(&self) -> &Computer {
+        &self.computer
+    }
+
+    pub fn computer_mut(&mut self) -> &mut Computer {
+        &mut self.computer
+    }
+
+    // Queue one line of input. The newline is added here.
+    pub fn send_line(&mut self, line: &str) {
+        for c in line.chars().chain(Some('\n')) {
+            self.computer.more_input(c as u8 as i64);
+        }
+    }
+
+    // Run until a complete line of output is available and return it,
+    // without the newline. Returns None if the program halts or wants
+    // input first; the partial line is then kept for the next call.
+    pub fn read_line(&mut self) -> Option<String> {
+        if !self.run_until(|text| text.contains('\n')) {
+            return None;
+        }
+        let end = self.pending.find('\n').unwrap();
+        let line = self.pending[..end].to_owned();
+        self.pending.drain(..=end);
+        Some(line)
+    }
+
+    // Run until the output ends with the prompt, and return all text up to
+    // and including the prompt. Returns None like read_line does.
+    pub fn read_until_prompt(&mut self, prompt: &str) -> Option<String> {
+        if !self.run_until(|text| text.ends_with(prompt)) {
+            return None;
+        }
+        Some(self.pending.drain(..).collect())
+    }
+
+    // Run until the program halts or wants input, and return all text it
+    // produced.
+    pub fn drain_text(&mut self) -> String {
+        self.run_until(|_| false);
+        self.pending.drain(..).collect()
+    }
+
+    // The last non-ASCII value the program produced, if any
+    pub fn answer(&self) -> Option<i64> {
+        self.answer
+    }
+
+    // Run the program, decoding its output, until the predicate holds for
+    // the pending text (true) or the program halts or needs input (false).
+    fn run_until<F: Fn(&str) -> bool>(&mut self, done: F) -> bool {
+        while !done(&self.pending) {
+            match self.computer.resume() {
+                StopReason::Output(value) if (0..128).contains(&value) => {
+                    self.pending.push(value as u8 as char)
+                }
+                StopReason::Output(value) => self.answer = Some(value),
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Echoes its input back until it reads a zero, then prints 1000
+    fn echo() -> AsciiComputer {
+        AsciiComputer::from(vec![
+            3, 100, 1006, 100, 11, 4, 100, 1105, 1, 0, 99, 104, 1000, 99,
+        ])
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut computer = echo();
+        assert_eq!(computer.read_line(), None);
+        computer.send_line("Hello");
+        computer.send_line("World? x");
+        assert_eq!(computer.read_line(), Some("Hello".to_owned()));
+        assert_eq!(computer.read_until_prompt("?"), Some("World?".to_owned()));
+        assert_eq!(computer.read_until_prompt("?"), None);
+        assert_eq!(computer.drain_text(), " x\n");
+        assert_eq!(computer.answer(), None);
+        computer.computer_mut().more_input(0);
+        assert_eq!(computer.drain_text(), "");
+        assert_eq!(computer.answer(), Some(1000));
+        assert!(computer.computer().is_halted());
+    }
+}
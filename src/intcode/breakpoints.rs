@@ -0,0 +1,315 @@
+use super::{Computer, Memory};
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+// Handle for a breakpoint or watchpoint, used to remove it again and to
+// tell which one stopped the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BreakId(usize);
+
+impl fmt::Display for BreakId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn includes(self, other: Access) -> bool {
+        self == Access::ReadWrite || self == other
+    }
+}
+
+// Which breakpoint or watchpoint stopped the machine. Breakpoints fire
+// before the instruction at <procnt> runs; watchpoints fire after the
+// instruction at <procnt> accessed <address>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hit {
+    Breakpoint {
+        id: BreakId,
+        procnt: i64,
+    },
+    Condition {
+        id: BreakId,
+        procnt: i64,
+    },
+    Watch {
+        id: BreakId,
+        procnt: i64,
+        address: i64,
+        access: Access,
+    },
+}
+
+type Predicate<M> = Arc<dyn Fn(&Computer<M>) -> bool + Send + Sync>;
+
+// All breakpoints of one Computer. The Computer only has one of these
+// once something is registered, so that unwatched machines pay nothing.
+#[derive(Clone, Default)]
+pub(super) struct Breakpoints<M: Memory> {
+    next_id: usize,
+    at: Vec<(BreakId, i64)>,
+    conditions: Vec<(BreakId, Predicate<M>)>,
+    watches: Vec<(BreakId, Range<i64>, Access)>,
+    // A watchpoint that fired during the last instruction
+    pending: Option<Hit>,
+    // Breakpoints at this address were just reported; let the
+    // instruction run when resuming. Cleared once any instruction runs.
+    resume_at: Option<i64>,
+}
+
+impl<M: Memory> fmt::Debug for Breakpoints<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Breakpoints")
+            .field("at", &self.at)
+            .field("conditions", &self.conditions.len())
+            .field("watches", &self.watches)
+            .finish()
+    }
+}
+
+impl<M: Memory> Breakpoints<M> {
+    fn new_id(&mut self) -> BreakId {
+        self.next_id += 1;
+        BreakId(self.next_id)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.at.is_empty() && self.conditions.is_empty() && self.watches.is_empty()
+    }
+}
+
+impl<M: Memory> Computer<M> {
+    // Stop before executing the instruction at <procnt>.
+    pub fn add_breakpoint(&mut self, procnt: i64) -> BreakId {
+        let breaks = self.breaks.get_or_insert_with(Default::default);
+        let id = breaks.new_id();
+        breaks.at.push((id, procnt));
+        id
+    }
+
+    // Stop before executing an instruction if the predicate holds for the
+    // machine in its current state.
+    pub fn add_condition<F>(&mut self, predicate: F) -> BreakId
+    where
+        F: Fn(&Computer<M>) -> bool + Send + Sync + 'static,
+    {
+        let breaks = self.breaks.get_or_insert_with(Default::default);
+        let id = breaks.new_id();
+        breaks.conditions.push((id, Arc::new(predicate)));
+        id
+    }
+
+    // Stop after an instruction reads or writes (as selected) any address
+    // in the range. Instruction fetches do not count as reads.
+    pub fn add_watchpoint(&mut self, addresses: Range<i64>, access: Access) -> BreakId {
+        let breaks = self.breaks.get_or_insert_with(Default::default);
+        let id = breaks.new_id();
+        breaks.watches.push((id, addresses, access));
+        id
+    }
+
+    // Remove a breakpoint or watchpoint. Returns false if it did not exist.
+    pub fn remove_break(&mut self, id: BreakId) -> bool {
+        let breaks = match &mut self.breaks {
+            Some(breaks) => breaks,
+            None => return false,
+        };
+        let before = breaks.at.len() + breaks.conditions.len() + breaks.watches.len();
+        breaks.at.retain(|b| b.0 != id);
+        breaks.conditions.retain(|b| b.0 != id);
+        breaks.watches.retain(|b| b.0 != id);
+        let removed = before != breaks.at.len() + breaks.conditions.len() + breaks.watches.len();
+        if breaks.is_empty() {
+            self.breaks = None;
+        }
+        removed
+    }
+
+    // The watchpoint that fired during the last instruction, if any. Once
+    // taken it is not reported again when resuming, so this is how to
+    // find out about watchpoints when stepping with try_step.
+    pub fn take_watch_hit(&mut self) -> Option<Hit> {
+        self.breaks.as_mut()?.pending.take()
+    }
+
+    pub fn clear_breaks(&mut self) {
+        self.breaks = None;
+    }
+
+    // Returns the hit that should stop the machine before the next
+    // instruction: a watchpoint that fired during the last one, or a
+    // breakpoint at the current one.
+    pub(super) fn check_breaks(&mut self) -> Option<Hit> {
+        let procnt = self.procnt;
+        let breaks = self.breaks.as_mut()?;
+        if let Some(hit) = breaks.pending.take() {
+            return Some(hit);
+        }
+        if breaks.resume_at == Some(procnt) {
+            return None;
+        }
+        let breaks = self.breaks.as_ref()?;
+        let hit = match breaks.at.iter().find(|b| b.1 == procnt) {
+            Some(&(id, _)) => Some(Hit::Breakpoint { id, procnt }),
+            None => breaks
+                .conditions
+                .iter()
+                .find(|b| (b.1)(self))
+                .map(|&(id, _)| Hit::Condition { id, procnt }),
+        };
+        if hit.is_some() {
+            self.breaks.as_mut()?.resume_at = Some(procnt);
+        }
+        hit
+    }
+
+    // Called by the VM after every instruction it executed
+    pub(super) fn ran_instruction(&mut self) {
+        if let Some(breaks) = &mut self.breaks {
+            breaks.resume_at = None;
+        }
+    }
+
+    // Called by the VM for every data access while breakpoints are set
+    pub(super) fn watch_access(&mut self, address: i64, access: Access) {
+        let procnt = self.procnt;
+        if let Some(breaks) = &mut self.breaks {
+            if breaks.pending.is_some() {
+                return;
+            }
+            breaks.pending = breaks
+                .watches
+                .iter()
+                .find(|w| w.1.contains(&address) && w.2.includes(access))
+                .map(|&(id, _, _)| Hit::Watch {
+                    id,
+                    procnt,
+                    address,
+                    access,
+                });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::StopReason;
+
+    // Counts down from the input, outputting every value
+    fn countdown(n: i64) -> Computer {
+        Computer::new(
+            vec![3, 100, 4, 100, 1001, 100, -1, 100, 1005, 100, 2, 99],
+            vec![n],
+        )
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut computer = countdown(3);
+        let id = computer.add_breakpoint(4);
+        assert_eq!(computer.resume(), StopReason::Output(3));
+        let hit = Hit::Breakpoint { id, procnt: 4 };
+        assert_eq!(computer.resume(), StopReason::Break(hit));
+        assert_eq!(computer.resume(), StopReason::Output(2));
+        assert_eq!(computer.resume(), StopReason::Break(hit));
+        assert!(computer.remove_break(id));
+        assert!(!computer.remove_break(id));
+        assert_eq!(computer.resume(), StopReason::Output(1));
+        assert_eq!(computer.resume(), StopReason::Halted);
+
+        // Stepping round the loop back to the breakpoint stops there again
+        let mut computer = countdown(3);
+        let id = computer.add_breakpoint(2);
+        let hit = Hit::Breakpoint { id, procnt: 2 };
+        assert_eq!(computer.resume(), StopReason::Break(hit));
+        for _ in 0..3 {
+            computer.try_step().unwrap();
+        }
+        assert_eq!(computer.procnt(), 2);
+        assert_eq!(computer.resume(), StopReason::Break(hit));
+
+        // So does a jump to itself
+        let mut computer = Computer::from(vec![1105, 1, 0]);
+        let id = computer.add_breakpoint(0);
+        let hit = Hit::Breakpoint { id, procnt: 0 };
+        assert_eq!(computer.resume(), StopReason::Break(hit));
+        assert_eq!(computer.resume(), StopReason::Break(hit));
+    }
+
+    #[test]
+    fn test_condition() {
+        let mut computer = countdown(3);
+        let id = computer.add_condition(|c| c.peek(100) == Some(1) && c.procnt() == 8);
+        let outputs: Vec<_> = std::iter::repeat_with(|| computer.resume())
+            .take(4)
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![
+                StopReason::Output(3),
+                StopReason::Output(2),
+                StopReason::Break(Hit::Condition { id, procnt: 8 }),
+                StopReason::Output(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        // Writes through the relative base
+        let mut computer = Computer::new(vec![109, 50, 203, 50, 99], vec![7]);
+        let id = computer.add_watchpoint(98..102, Access::Write);
+        computer.add_watchpoint(0..10, Access::Read);
+        assert_eq!(
+            computer.resume(),
+            StopReason::Break(Hit::Watch {
+                id,
+                procnt: 2,
+                address: 100,
+                access: Access::Write
+            })
+        );
+        assert_eq!(computer.procnt(), 4);
+        assert_eq!(computer.resume(), StopReason::Halted);
+
+        let mut computer = countdown(1);
+        let id = computer.add_watchpoint(100..101, Access::Read);
+        assert_eq!(computer.resume(), StopReason::Output(1));
+        assert_eq!(
+            computer.resume(),
+            StopReason::Break(Hit::Watch {
+                id,
+                procnt: 2,
+                address: 100,
+                access: Access::Read
+            })
+        );
+
+        // A hit while stepping is taken, instead of stopping the next run
+        let mut computer = countdown(1);
+        let id = computer.add_watchpoint(100..101, Access::Read);
+        computer.try_step().unwrap();
+        assert_eq!(computer.take_watch_hit(), None);
+        computer.try_step().unwrap();
+        assert!(computer.take_watch_hit().is_some());
+        assert_eq!(computer.take_watch_hit(), None);
+        assert_eq!(
+            computer.resume(),
+            StopReason::Break(Hit::Watch {
+                id,
+                procnt: 4,
+                address: 100,
+                access: Access::Read
+            })
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use super::{Computer, IntcodeError, Memory};
+
+// Resource limits for a Computer. None means no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    // Number of instructions executed
+    pub instructions: Option<u64>,
+    // Size of the memory, in values
+    pub memory: Option<usize>,
+    // Number of values output
+    pub output: Option<u64>,
+}
+
+// Which limit would have been exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Instructions,
+    Memory,
+    Output,
+}
+
+// Limits and what has been used of them so far
+#[derive(Debug, Clone, Default)]
+pub(super) struct Budget {
+    limits: Limits,
+    executed: u64,
+    produced: u64,
+}
+
+impl<M: Memory> Computer<M> {
+    // Limit what the program may use from now on. An instruction that
+    // would go over a limit is not executed; resume() stops with
+    // StopReason::Limit instead, and try_step and try_run report it as a
+    // fault. The machine can still continue after raising the limit.
+    pub fn set_limits(&mut self, limits: Limits) {
+        let budget = self.budget.get_or_insert_with(Default::default);
+        budget.limits = limits;
+    }
+
+    pub fn clear_limits(&mut self) {
+        self.budget = None;
+    }
+
+    // Instructions executed and values output since limits were first set
+    pub fn usage(&self) -> (u64, u64) {
+        self.budget
+            .as_ref()
+            .map_or((0, 0), |budget| (budget.executed, budget.produced))
+    }
+
+    // Called before every instruction
+    pub(super) fn check_instructions(&self) -> Result<(), IntcodeError> {
+        match &self.budget {
+            Some(b) if b.limits.instructions.is_some_and(|max| b.executed >= max) => {
+                Err(IntcodeError::LimitExceeded(Limit::Instructions))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Called for every address written to
+    pub(super) fn check_memory(&self, address: i64) -> Result<(), IntcodeError> {
+        let limit = match self.budget.as_ref().and_then(|b| b.limits.memory) {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let address = address as usize;
+        if address >= limit && address >= self.memory.len() {
+            return Err(IntcodeError::LimitExceeded(Limit::Memory));
+        }
+        Ok(())
+    }
+
+    // Called before every output
+    pub(super) fn check_output(&self) -> Result<(), IntcodeError> {
+        match &self.budget {
+            Some(b) if b.limits.output.is_some_and(|max| b.produced >= max) => {
+                Err(IntcodeError::LimitExceeded(Limit::Output))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Account for an instruction that was executed
+    pub(super) fn charge(&mut self) {
+        if let Some(budget) = &mut self.budget {
+            budget.executed += 1;
+        }
+    }
+
+    // Account for a value that was output
+    pub(super) fn charge_output(&mut self) {
+        if let Some(budget) = &mut self.budget {
+            budget.produced += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::StopReason;
+
+    #[test]
+    fn test_limits() {
+        // Outputs 1, 2, 3, ... forever
+        let mut computer = Computer::from(vec![101, 1, 9, 9, 4, 9, 1105, 1, 0, 0]);
+        computer.set_limits(Limits {
+            instructions: Some(10),
+            ..Default::default()
+        });
+        let mut outputs = Vec::new();
+        while let StopReason::Output(value) = computer.resume() {
+            outputs.push(value);
+        }
+        assert_eq!(outputs, vec![1, 2, 3]);
+        assert_eq!(computer.resume(), StopReason::Limit(Limit::Instructions));
+        assert_eq!(computer.usage(), (10, 3));
+        assert_eq!(computer.procnt(), 4);
+        computer.set_limits(Limits {
+            output: Some(5),
+            ..Default::default()
+        });
+        assert_eq!(computer.resume(), StopReason::Output(4));
+        assert_eq!(computer.resume(), StopReason::Output(5));
+        assert_eq!(computer.resume(), StopReason::Limit(Limit::Output));
+        assert_eq!(
+            computer.try_step().unwrap_err().error,
+            IntcodeError::LimitExceeded(Limit::Output)
+        );
+
+        // Writes to ever higher addresses: 100, 150, 200, ...
+        let mut computer = Computer::from(vec![1101, 1, 2, 100, 1001, 3, 50, 3, 1105, 1, 0]);
+        computer.set_limits(Limits {
+            memory: Some(250),
+            ..Default::default()
+        });
+        assert_eq!(computer.resume(), StopReason::Limit(Limit::Memory));
+        assert_eq!(computer.memory().len(), 201);
+        assert_eq!(computer.procnt(), 0);
+    }
+}
@@ -0,0 +1,218 @@
+use super::{Computer, Instruction, Memory, Mode};
+use crate::intcode_aot::find_code;
+use crate::intcode_asm::{Debugger, Op};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Which ways a conditional jump has gone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Branch {
+    pub jumped: bool,
+    pub fell_through: bool,
+}
+
+// The instructions and jump directions that were executed, over one or
+// more runs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    // Keyed by address, so that code far into paged memory costs no more
+    // than code at the start
+    executed: BTreeSet<usize>,
+    branches: BTreeMap<usize, Branch>,
+}
+
+impl<M: Memory> Computer<M> {
+    // Record every instruction executed from now on. Starts over if the
+    // computer was already recording coverage.
+    pub fn start_coverage(&mut self) {
+        self.coverage = Some(Box::new(Coverage::default()));
+    }
+
+    // Stop recording and hand over what was covered
+    pub fn stop_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take().map(|coverage| *coverage)
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_deref()
+    }
+
+    // Called after every instruction that was executed at <procnt>. A
+    // jump to the next instruction counts as falling through.
+    pub(super) fn cover(&mut self, procnt: i64, ins: &Instruction) {
+        let next = procnt + ins.length() as i64;
+        let jumped = self.procnt != next;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.cover(procnt as usize, ins.opcode, jumped);
+        }
+    }
+}
+
+impl Coverage {
+    fn cover(&mut self, address: usize, opcode: i64, jumped: bool) {
+        self.executed.insert(address);
+        if opcode == 5 || opcode == 6 {
+            let branch = self.branches.entry(address).or_default();
+            if jumped {
+                branch.jumped = true;
+            } else {
+                branch.fell_through = true;
+            }
+        }
+    }
+
+    // Add what another run covered
+    pub fn merge(&mut self, other: &Coverage) {
+        for &address in &other.executed {
+            if let Some(&branch) = other.branches.get(&address) {
+                if branch.jumped {
+                    self.cover(address, 5, true);
+                }
+                if branch.fell_through {
+                    self.cover(address, 5, false);
+                }
+            } else {
+                self.cover(address, 0, false);
+            }
+        }
+    }
+
+    pub fn is_executed(&self, address: usize) -> bool {
+        self.executed.contains(&address)
+    }
+
+    // The directions taken by the jump at <address>, if it was executed
+    pub fn branch(&self, address: usize) -> Option<Branch> {
+        self.branches.get(&address).copied()
+    }
+
+    // The addresses of the instructions in <program>: those reachable
+    // from the start without running it, and those that were executed
+    fn code(&self, program: &[i64]) -> BTreeSet<usize> {
+        let code = find_code(program);
+        let reachable = (0..code.len()).filter(|&address| code[address].is_some());
+        reachable.chain(self.executed.iter().copied()).collect()
+    }
+
+    // Instructions and branch directions covered, out of those in the
+    // code of <program>: ((covered, total), (covered, total)). Only jumps
+    // that test a value from memory have two directions.
+    pub fn summary(&self, program: &[i64]) -> ((usize, usize), (usize, usize)) {
+        let mut instructions = (0, 0);
+        let mut branches = (0, 0);
+        for address in self.code(program) {
+            instructions.1 += 1;
+            if self.is_executed(address) {
+                instructions.0 += 1;
+            }
+            let op = match Op::decode(program, address) {
+                Some(op) => op,
+                None => continue,
+            };
+            if (op.opcode == 5 || op.opcode == 6) && op.params[0].1 != Mode::Immediate {
+                branches.1 += 2;
+                let branch = self.branch(address).unwrap_or_default();
+                branches.0 += branch.jumped as usize + branch.fell_through as usize;
+            }
+        }
+        (instructions, branches)
+    }
+
+    // The summary, followed by the listing of the code of <program>, one
+    // line per instruction. In front of every instruction is * if it was
+    // executed and . if not; conditional jumps also show J if they jumped
+    // and F if they fell through.
+    pub fn report(&self, program: &[i64]) -> String {
+        let ((ins, ins_total), (br, br_total)) = self.summary(program);
+        let percent = |n: usize, total: usize| 100.0 * n as f64 / total.max(1) as f64;
+        let mut result = format!(
+            "Instructions: {}/{} ({:.1}%)\nBranches: {}/{} ({:.1}%)\n\n",
+            ins,
+            ins_total,
+            percent(ins, ins_total),
+            br,
+            br_total,
+            percent(br, br_total)
+        );
+        let code = self.code(program);
+        result += &Debugger::from(program.to_vec()).annotated_listing(&code, |address| {
+            let mark = if self.is_executed(address) { '*' } else { '.' };
+            let branch = match self.branch(address) {
+                Some(b) => format!(
+                    "{}{}",
+                    if b.jumped { 'J' } else { '-' },
+                    if b.fell_through { 'F' } else { '-' }
+                ),
+                None => "  ".to_owned(),
+            };
+            format!("{} {} ", mark, branch)
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::PagedMemory;
+
+    #[test]
+    fn test_coverage() {
+        // Outputs 1 if the input is not zero, 0 otherwise
+        let program = vec![3, 11, 1006, 11, 8, 104, 1, 99, 104, 0, 99, 0];
+        let run = |input| {
+            let mut computer = Computer::new(program.clone(), vec![input]);
+            computer.start_coverage();
+            computer.run();
+            computer.stop_coverage().unwrap()
+        };
+        let mut coverage = run(5);
+        assert!(coverage.is_executed(5));
+        assert!(!coverage.is_executed(8));
+        assert_eq!(
+            coverage.branch(2),
+            Some(Branch {
+                jumped: false,
+                fell_through: true
+            })
+        );
+        assert_eq!(coverage.summary(&program), ((4, 6), (1, 2)));
+        assert!(coverage.report(&program).contains(".       8: output(0)\n"));
+
+        coverage.merge(&run(0));
+        assert_eq!(coverage.summary(&program), ((6, 6), (2, 2)));
+        let report = coverage.report(&program);
+        assert!(report.starts_with("Instructions: 6/6 (100.0%)\nBranches: 2/2 (100.0%)\n"));
+        assert!(report.contains("* JF    2: if *11 == 0 { goto 8 }\n"));
+    }
+
+    #[test]
+    fn test_day9() {
+        // The code of the BOOST program goes on past data at address 63
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/input/2019/day9.txt");
+        let program = crate::intcode::Program::load(path).unwrap();
+        let mut computer = Computer::new(program.to_vec(), vec![1]);
+        computer.start_coverage();
+        computer.run();
+        let coverage = computer.stop_coverage().unwrap();
+        let executed = (0..program.len())
+            .filter(|&address| coverage.is_executed(address))
+            .count();
+        let ((ins, ins_total), _) = coverage.summary(&program);
+        assert_eq!(ins, executed);
+        assert!(ins_total > 63 && ins_total >= ins);
+        let report = coverage.report(&program);
+        let listed = report.lines().filter(|line| line.starts_with('*')).count();
+        assert_eq!(listed, executed);
+    }
+
+    #[test]
+    fn test_far_code() {
+        // Writes a halt far into paged memory and jumps there
+        let program = vec![1101, 99, 0, 1 << 40, 1105, 1, 1 << 40];
+        let mut computer = Computer::with_memory(PagedMemory::from(program));
+        computer.start_coverage();
+        computer.run();
+        let coverage = computer.stop_coverage().unwrap();
+        assert!(coverage.is_executed(1 << 40));
+    }
+}
@@ -0,0 +1,385 @@
+// Differential tests: random programs are run on the Computer and on a
+// reference interpreter that is as simple as possible, and everything
+// observable has to agree. Any change to how the Computer executes
+// (decoding, caching, memory) should keep these passing.
+
+use super::{Computer, Limits, Memory, PagedMemory, StopReason};
+use std::collections::VecDeque;
+
+// Generated programs keep their variables here, counters just after
+const DATA: i64 = 1000;
+const CELLS: i64 = 8;
+const COUNTERS: i64 = DATA + CELLS;
+// The relative base starts here, with room below for negative offsets
+const STACK: i64 = 2000;
+// More than any generated program needs
+const STEP_LIMIT: u64 = 1_000_000;
+
+// xorshift64*, enough to make programs from a seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // A number in from..to
+    fn range(&mut self, from: i64, to: i64) -> i64 {
+        from + (self.next() % (to - from) as u64) as i64
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+}
+
+// The kinds of program the generator makes
+#[derive(Debug, Clone, Copy)]
+enum Shape {
+    StraightLine,
+    Loops,
+    Stack,
+}
+
+// A random program and the input it will read
+#[derive(Debug)]
+struct Generated {
+    program: Vec<i64>,
+    input: Vec<i64>,
+}
+
+struct Generator {
+    rng: Rng,
+    code: Vec<i64>,
+    input: Vec<i64>,
+    // Values pushed on the stack and not popped yet
+    depth: i64,
+    // How often the code being generated will run, at most
+    repeat: i64,
+}
+
+impl Generator {
+    fn generate(seed: u64, shape: Shape) -> Generated {
+        let mut gen = Generator {
+            rng: Rng::new(seed),
+            code: vec![109, STACK],
+            input: Vec::new(),
+            depth: 0,
+            repeat: 1,
+        };
+        let length = gen.rng.range(5, 25);
+        for _ in 0..length {
+            match shape {
+                Shape::StraightLine => gen.statement(),
+                Shape::Loops => match gen.rng.range(0, 3) {
+                    0 => gen.counted_loop(0),
+                    _ => gen.statement(),
+                },
+                Shape::Stack => match gen.rng.range(0, 3) {
+                    0 => gen.push(),
+                    1 if gen.depth > 0 => gen.pop(),
+                    _ => gen.statement(),
+                },
+            }
+        }
+        // Show the final state of every variable
+        for cell in 0..CELLS {
+            gen.code.extend(&[4, DATA + cell]);
+        }
+        gen.code.push(99);
+        Generated {
+            program: gen.code,
+            input: gen.input,
+        }
+    }
+
+    // A parameter to read: a variable, a constant or a stack slot
+    fn operand(&mut self) -> (i64, i64) {
+        match self.rng.range(0, 4) {
+            0 | 1 => (0, DATA + self.rng.range(0, CELLS)),
+            2 => (1, self.rng.range(-20, 20)),
+            _ => (2, self.rng.range(-3, 1)),
+        }
+    }
+
+    // A parameter to write: a variable or a stack slot
+    fn destination(&mut self) -> (i64, i64) {
+        match self.rng.range(0, 3) {
+            0 | 1 => (0, DATA + self.rng.range(0, CELLS)),
+            _ => (2, self.rng.range(-3, 1)),
+        }
+    }
+
+    fn instruction(&mut self, opcode: i64, params: &[(i64, i64)]) {
+        let modes: i64 = params
+            .iter()
+            .rev()
+            .fold(0, |modes, &(mode, _)| modes * 10 + mode);
+        self.code.push(modes * 100 + opcode);
+        self.code.extend(params.iter().map(|&(_, operand)| operand));
+    }
+
+    // One instruction without control flow, sometimes skipped by a jump
+    // on a variable being zero or not
+    fn statement(&mut self) {
+        if self.rng.chance(20) {
+            let test = self.operand();
+            let opcode = self.rng.range(5, 7);
+            let jump = self.code.len();
+            self.instruction(opcode, &[test, (1, 0)]);
+            self.statement();
+            let target = self.code.len() as i64;
+            self.code[jump + 2] = target;
+            return;
+        }
+        match self.rng.range(0, 10) {
+            0 => {
+                let dest = self.destination();
+                self.instruction(3, &[dest]);
+                for _ in 0..self.repeat {
+                    let value = self.rng.range(-100, 100);
+                    self.input.push(value);
+                }
+            }
+            1 => {
+                let value = self.operand();
+                self.instruction(4, &[value]);
+            }
+            _ => {
+                let opcode = [1, 2, 7, 8][self.rng.range(0, 4) as usize];
+                let (a, b, dest) = (self.operand(), self.operand(), self.destination());
+                self.instruction(opcode, &[a, b, dest]);
+            }
+        }
+    }
+
+    // Repeat some statements 1 to 5 times, possibly with a loop inside
+    fn counted_loop(&mut self, level: i64) {
+        let counter = COUNTERS + level;
+        let times = self.rng.range(1, 6);
+        self.instruction(1, &[(1, times), (1, 0), (0, counter)]);
+        let start = self.code.len() as i64;
+        self.repeat *= times;
+        for _ in 0..self.rng.range(1, 5) {
+            if level == 0 && self.rng.chance(25) {
+                self.counted_loop(level + 1);
+            } else {
+                self.statement();
+            }
+        }
+        self.repeat /= times;
+        self.instruction(1, &[(0, counter), (1, -1), (0, counter)]);
+        self.instruction(5, &[(0, counter), (1, start)]);
+    }
+
+    // rb[0] := value; rb += 1
+    fn push(&mut self) {
+        let value = self.operand();
+        self.instruction(1, &[value, (1, 0), (2, 0)]);
+        self.instruction(9, &[(1, 1)]);
+        self.depth += 1;
+    }
+
+    // rb -= 1; variable := rb[0] op rb[-1]
+    fn pop(&mut self) {
+        self.instruction(9, &[(1, -1)]);
+        let opcode = [1, 7, 8][self.rng.range(0, 3) as usize];
+        let dest = (0, DATA + self.rng.range(0, CELLS));
+        self.instruction(opcode, &[(2, 0), (2, -1), dest]);
+        self.depth -= 1;
+    }
+}
+
+// What a run leaves behind
+#[derive(Debug, PartialEq, Eq)]
+struct Outcome {
+    memory: Vec<i64>,
+    output: Vec<i64>,
+    steps: u64,
+}
+
+// The reference: decodes every instruction from scratch, one digit at a
+// time, reads zero past the end of memory and grows it on writes. Fails
+// on anything unusual, including overflow, so that such programs can be
+// skipped.
+fn reference(program: &[i64], input: &[i64]) -> Result<Outcome, String> {
+    let mut memory = program.to_vec();
+    let mut input: VecDeque<i64> = input.iter().copied().collect();
+    let mut output = Vec::new();
+    let (mut pc, mut rb, mut steps) = (0, 0, 0);
+    loop {
+        steps += 1;
+        if steps > STEP_LIMIT {
+            return Err("Too many steps".to_owned());
+        }
+        let word = *memory.get(pc as usize).ok_or("PC outside memory")?;
+        let opcode = word % 100;
+        let address = |idx: i64, rb: i64, memory: &Vec<i64>| -> Result<Option<i64>, String> {
+            let mode = word / 10_i64.pow(idx as u32 + 1) % 10;
+            let operand = *memory.get((pc + idx) as usize).unwrap_or(&0);
+            let address = match mode {
+                0 => operand,
+                1 => return Ok(None),
+                2 => rb + operand,
+                _ => return Err(format!("Mode {}", mode)),
+            };
+            if address < 0 {
+                return Err("Negative address".to_owned());
+            }
+            Ok(Some(address))
+        };
+        let read = |idx: i64, rb: i64, memory: &Vec<i64>| -> Result<i64, String> {
+            Ok(match address(idx, rb, memory)? {
+                Some(a) => *memory.get(a as usize).unwrap_or(&0),
+                None => *memory.get((pc + idx) as usize).unwrap_or(&0),
+            })
+        };
+        let write = |idx: i64, rb: i64, memory: &mut Vec<i64>, value: i64| {
+            let a = address(idx, rb, memory)?.ok_or("Immediate write")? as usize;
+            if memory.len() <= a {
+                memory.resize(a + 1, 0);
+            }
+            memory[a] = value;
+            Ok::<(), String>(())
+        };
+        match opcode {
+            1 | 2 | 7 | 8 => {
+                let (a, b) = (read(1, rb, &memory)?, read(2, rb, &memory)?);
+                let value = match opcode {
+                    1 => a.checked_add(b).ok_or("Overflow")?,
+                    2 => a.checked_mul(b).ok_or("Overflow")?,
+                    7 => (a < b) as i64,
+                    _ => (a == b) as i64,
+                };
+                write(3, rb, &mut memory, value)?;
+                pc += 4;
+            }
+            3 => {
+                let value = input.pop_front().ok_or("No input")?;
+                write(1, rb, &mut memory, value)?;
+                pc += 2;
+            }
+            4 => {
+                output.push(read(1, rb, &memory)?);
+                pc += 2;
+            }
+            5 | 6 => {
+                let (test, target) = (read(1, rb, &memory)?, read(2, rb, &memory)?);
+                pc = if (test != 0) == (opcode == 5) {
+                    target
+                } else {
+                    pc + 3
+                };
+            }
+            9 => {
+                rb += read(1, rb, &memory)?;
+                pc += 2;
+            }
+            99 => {
+                return Ok(Outcome {
+                    memory,
+                    output,
+                    steps,
+                })
+            }
+            n => return Err(format!("Opcode {}", n)),
+        }
+    }
+}
+
+// Run on a Computer with the given memory, either all at once or in
+// short slices through resume
+fn computer<M: Memory>(program: &[i64], input: &[i64], slices: bool) -> Outcome {
+    let mut computer = Computer::with_memory(M::from(program.to_vec()));
+    for &value in input {
+        computer.more_input(value);
+    }
+    computer.set_limits(Limits {
+        instructions: Some(STEP_LIMIT),
+        ..Default::default()
+    });
+    let mut output = Vec::new();
+    if slices {
+        loop {
+            match computer.try_resume_for(7) {
+                Ok(StopReason::Output(value)) => output.push(value),
+                Ok(StopReason::StepLimit) => {}
+                Ok(StopReason::Halted) => break,
+                other => panic!("{:?}", other),
+            }
+        }
+    } else {
+        output = computer.try_run().cloned().unwrap();
+    }
+    Outcome {
+        memory: computer.memory.to_vec(),
+        output,
+        steps: computer.usage().0,
+    }
+}
+
+// Compare every way of running the program with the reference. Returns
+// false if the program is no good as a test.
+fn check(seed: u64, shape: Shape) -> bool {
+    let Generated { program, input } = Generator::generate(seed, shape);
+    let expected = match reference(&program, &input) {
+        Ok(outcome) => outcome,
+        Err(_) => return false,
+    };
+    let context = format!(
+        "{:?} program {} {:?} with input {:?}",
+        shape, seed, program, input
+    );
+    assert_eq!(
+        computer::<Vec<i64>>(&program, &input, false),
+        expected,
+        "{}",
+        context
+    );
+    assert_eq!(
+        computer::<Vec<i64>>(&program, &input, true),
+        expected,
+        "{}",
+        context
+    );
+    assert_eq!(
+        computer::<PagedMemory>(&program, &input, false),
+        expected,
+        "{}",
+        context
+    );
+    true
+}
+
+#[test]
+fn test_against_reference() {
+    for &shape in &[Shape::StraightLine, Shape::Loops, Shape::Stack] {
+        let checked = (0..300).filter(|&seed| check(seed, shape)).count();
+        // Overflow should only rule out a few
+        assert!(
+            checked > 250,
+            "{:?}: only {} programs usable",
+            shape,
+            checked
+        );
+    }
+}
+
+#[test]
+fn test_reference() {
+    // Examples from day 9, including a quine
+    let quine = vec![
+        109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+    ];
+    assert_eq!(reference(&quine, &[]).unwrap().output, quine);
+    let big = reference(&[104, 1_125_899_906_842_624, 99], &[]).unwrap();
+    assert_eq!((big.output, big.steps), (vec![1_125_899_906_842_624], 2));
+    assert!(reference(&[2, 0, 0, 0, 2, 0, 0, 0, 1105, 1, 0], &[]).is_err());
+}
@@ -0,0 +1,137 @@
+use super::{Computer, Memory};
+
+// Everything needed to undo one instruction: the registers before it ran,
+// the memory cell it overwrote and how much I/O it did.
+#[derive(Debug, Clone)]
+pub(super) struct Undo {
+    procnt: i64,
+    relbse: i64,
+    halted: bool,
+    // Address, old value and memory size before the write
+    write: Option<(usize, i64, usize)>,
+    // Input value that was consumed
+    input: Option<i64>,
+    output_len: usize,
+}
+
+impl<M: Memory> Computer<M> {
+    // Start keeping an undo log, so that the machine can step backwards.
+    // The log starts empty; history from before this call is not known.
+    pub fn start_recording(&mut self) {
+        self.history = Some(Vec::new());
+    }
+
+    // Stop keeping an undo log and throw away the one we have.
+    pub fn stop_recording(&mut self) {
+        self.history = None;
+    }
+
+    // Number of instructions that can be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, Vec::len)
+    }
+
+    // Undo the last instruction. Returns false if there is nothing to
+    // undo. Output that was already taken from the buffer or sent to a
+    // sink stays taken.
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self.history.as_mut().and_then(Vec::pop) {
+            Some(undo) => undo,
+            None => return false,
+        };
+        if let Some((address, value, len)) = undo.write {
+            self.set(address as i64, value);
+            self.memory.truncate(len);
+            self.decoded.truncate(len);
+        }
+        if let Some(value) = undo.input {
+            self.input.push_front(value);
+        }
+        self.output.truncate(undo.output_len);
+        self.procnt = undo.procnt;
+        self.relbse = undo.relbse;
+        self.halted = undo.halted;
+        true
+    }
+
+    // Step back until the program counter is at <pc>. Returns false if
+    // the history runs out first, leaving the machine at the oldest state
+    // that was recorded.
+    pub fn run_back_to(&mut self, pc: i64) -> bool {
+        while self.step_back() {
+            if self.procnt == pc {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Record the state the instruction at the program counter is about to
+    // change. If it can not be decoded, it will fault and not be logged.
+    pub(super) fn undo_entry(&mut self) -> Undo {
+        let mut undo = Undo {
+            procnt: self.procnt,
+            relbse: self.relbse,
+            halted: self.halted,
+            write: None,
+            input: None,
+            output_len: self.output.len(),
+        };
+        if let Ok(ins) = self.instruction() {
+            if let Some(address) = ins
+                .write_param()
+                .and_then(|idx| self.destination(&ins, idx).ok())
+            {
+                let old = self.memory.peek(address as usize);
+                undo.write = Some((address as usize, old, self.memory.len()));
+            }
+            if ins.opcode == 3 {
+                undo.input = self.input.front().copied();
+            }
+        }
+        undo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::StopReason;
+
+    #[test]
+    fn test_step_back() {
+        // Reads a number, stores it far away, outputs its double
+        let program = vec![3, 20, 109, 5, 21002, 20, 2, 95, 4, 100, 99];
+        let mut computer = Computer::new(program.clone(), vec![21]);
+        computer.start_recording();
+        computer.run();
+        assert_eq!(computer.output, vec![42]);
+        assert_eq!(computer.history_len(), 5);
+
+        assert!(computer.run_back_to(4));
+        assert_eq!(computer.memory.len(), 21);
+        assert_eq!(computer.output, vec![]);
+        assert_eq!(computer.relbse, 5);
+        while computer.step_back() {}
+        assert_eq!(computer.memory, program);
+        assert_eq!((computer.procnt, computer.relbse), (0, 0));
+        assert!(!computer.run_back_to(4));
+
+        // Replaying gives the same result
+        assert_eq!(computer.resume(), StopReason::Output(42));
+    }
+
+    #[test]
+    fn test_step_back_code() {
+        // Writes its input into the operand of the output instruction
+        let mut computer = Computer::new(vec![3, 7, 1105, 1, 6, 99, 104, 0, 99], vec![5]);
+        computer.start_recording();
+        assert_eq!(computer.resume(), StopReason::Output(5));
+        assert!(computer.decoded[6].is_some());
+        assert!(computer.run_back_to(0));
+        assert_eq!(computer.memory[7], 0);
+        assert!(computer.decoded[6].is_none());
+        computer.input = vec![8].into();
+        assert_eq!(computer.resume(), StopReason::Output(8));
+    }
+}
@@ -0,0 +1,240 @@
+use super::{Computer, Memory, StopReason};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+// Where a Computer gets its input once its own buffer is empty. None
+// means there is nothing right now; the Computer then waits for input.
+pub trait InputSource {
+    fn next_input(&mut self) -> Option<i64>;
+}
+
+// Where a Computer puts its output instead of its own buffer.
+pub trait OutputSink {
+    fn send_output(&mut self, value: i64);
+}
+
+// An attached source or sink. Clones of a Computer do not share these,
+// so a clone starts out detached.
+pub(super) struct Port<T: ?Sized>(pub(super) Option<Box<T>>);
+
+impl<T: ?Sized> Default for Port<T> {
+    fn default() -> Self {
+        Port(None)
+    }
+}
+
+impl<T: ?Sized> Clone for Port<T> {
+    fn clone(&self) -> Self {
+        Port(None)
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Port<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "attached"),
+            None => write!(f, "detached"),
+        }
+    }
+}
+
+impl<M: Memory> Computer<M> {
+    // Take input from the source whenever the input buffer is empty.
+    pub fn read_from<S: InputSource + Send + 'static>(&mut self, source: S) {
+        self.source = Port(Some(Box::new(source)));
+    }
+
+    // Send all output to the sink instead of the output buffer. Since
+    // nothing arrives in the buffer any more, resume() no longer stops
+    // for output.
+    pub fn write_to<S: OutputSink + Send + 'static>(&mut self, sink: S) {
+        self.sink = Port(Some(Box::new(sink)));
+    }
+
+    // Go back to using only the internal buffers.
+    pub fn detach_io(&mut self) {
+        self.source = Port(None);
+        self.sink = Port(None);
+    }
+
+    // Refill the input buffer from the source, if it is empty. Returns
+    // false if there is no input to be had.
+    pub(super) fn fill_input(&mut self) -> bool {
+        if self.input.is_empty() {
+            if let Some(value) = self.source.0.as_mut().and_then(|s| s.next_input()) {
+                self.input.push_back(value);
+            }
+        }
+        !self.input.is_empty()
+    }
+
+    // Put one output value wherever it should go
+    pub(super) fn emit(&mut self, value: i64) {
+        match self.sink.0.as_mut() {
+            Some(sink) => sink.send_output(value),
+            None => self.output.push(value),
+        }
+    }
+}
+
+impl InputSource for VecDeque<i64> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+impl OutputSink for VecDeque<i64> {
+    fn send_output(&mut self, value: i64) {
+        self.push_back(value)
+    }
+}
+
+impl OutputSink for Vec<i64> {
+    fn send_output(&mut self, value: i64) {
+        self.push(value)
+    }
+}
+
+// Input from any iterator of values
+pub struct IterInput<I>(pub I);
+
+impl<I: Iterator<Item = i64>> InputSource for IterInput<I> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.0.next()
+    }
+}
+
+// Input computed by a closure, output handed to a closure
+pub struct FnInput<F>(pub F);
+pub struct FnOutput<F>(pub F);
+
+impl<F: FnMut() -> Option<i64>> InputSource for FnInput<F> {
+    fn next_input(&mut self) -> Option<i64> {
+        (self.0)()
+    }
+}
+
+impl<F: FnMut(i64)> OutputSink for FnOutput<F> {
+    fn send_output(&mut self, value: i64) {
+        (self.0)(value)
+    }
+}
+
+// Channels never block the Computer: an empty channel means it waits
+// for input, and output to a closed channel is dropped.
+impl InputSource for Receiver<i64> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.try_recv().ok()
+    }
+}
+
+impl OutputSink for Sender<i64> {
+    fn send_output(&mut self, value: i64) {
+        let _ = self.send(value);
+    }
+}
+
+// Another Computer is a source of its output: it runs until it produces
+// the next value. Once it faults it has no more output, as if it were
+// waiting for input. A Computer is a sink that buffers the values as
+// input.
+impl<M: Memory> InputSource for Computer<M> {
+    fn next_input(&mut self) -> Option<i64> {
+        match self.try_resume() {
+            Ok(StopReason::Output(value)) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<M: Memory> OutputSink for Computer<M> {
+    fn send_output(&mut self, value: i64) {
+        self.more_input(value)
+    }
+}
+
+// Shared handles, so that a source or sink can still be used (or be
+// attached to several Computers) after attaching it
+impl<T: InputSource + ?Sized> InputSource for Arc<Mutex<T>> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.lock().unwrap().next_input()
+    }
+}
+
+impl<T: OutputSink + ?Sized> OutputSink for Arc<Mutex<T>> {
+    fn send_output(&mut self, value: i64) {
+        self.lock().unwrap().send_output(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    // Doubles every input
+    fn doubler() -> Computer {
+        Computer::from(vec![3, 9, 102, 2, 9, 9, 4, 9, 1105, 1, 0])
+    }
+
+    #[test]
+    fn test_sources() {
+        let mut computer = doubler();
+        computer.read_from(IterInput(1..=3));
+        assert_eq!(computer.resume(), StopReason::Output(2));
+        computer.more_input(10);
+        let outputs: Vec<_> = std::iter::repeat_with(|| computer.resume())
+            .take(4)
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![
+                StopReason::Output(20),
+                StopReason::Output(4),
+                StopReason::Output(6),
+                StopReason::NeedsInput
+            ]
+        );
+
+        let mut n = 0;
+        let mut computer = doubler();
+        computer.read_from(FnInput(move || {
+            n += 1;
+            Some(n)
+        }));
+        assert_eq!(computer.resume_for(20), StopReason::Output(2));
+    }
+
+    #[test]
+    fn test_sinks() {
+        let sink = Arc::new(Mutex::new(VecDeque::new()));
+        let mut computer = doubler();
+        computer.read_from(VecDeque::from(vec![4, 5]));
+        computer.write_to(sink.clone());
+        assert_eq!(computer.resume(), StopReason::NeedsInput);
+        assert_eq!(*sink.lock().unwrap(), vec![8, 10]);
+    }
+
+    #[test]
+    fn test_channels_and_chains() {
+        let (tx, rx) = channel();
+        let (out_tx, out_rx) = channel();
+        let mut first = doubler();
+        first.read_from(rx);
+        let mut second = doubler();
+        second.read_from(first);
+        second.write_to(out_tx);
+        tx.send(3).unwrap();
+        tx.send(4).unwrap();
+        assert_eq!(second.resume(), StopReason::NeedsInput);
+        assert_eq!(out_rx.try_iter().collect::<Vec<_>>(), vec![12, 16]);
+
+        // A source that faults gives no input instead of panicking
+        let mut computer = doubler();
+        computer.read_from(Computer::from(vec![104, 1, 42]));
+        assert_eq!(computer.resume(), StopReason::Output(2));
+        assert_eq!(computer.resume(), StopReason::NeedsInput);
+    }
+}
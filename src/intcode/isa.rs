@@ -0,0 +1,416 @@
+use super::{Computer, Instruction, IntcodeError, Memory, Mode};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+// Name, number of parameters and the parameter written to, if any, of
+// every opcode the Computer knows by itself
+const BUILTINS: [(i64, &str, usize, Option<usize>); 10] = [
+    (1, "add", 3, Some(2)),
+    (2, "mul", 3, Some(2)),
+    (3, "in", 1, Some(0)),
+    (4, "out", 1, None),
+    (5, "jnz", 2, None),
+    (6, "jz", 2, None),
+    (7, "lt", 3, Some(2)),
+    (8, "eq", 3, Some(2)),
+    (9, "arb", 1, None),
+    (99, "halt", 0, None),
+];
+
+// Name, arity and write parameter of a built-in opcode
+pub(crate) fn builtin(opcode: i64) -> Option<(&'static str, usize, Option<usize>)> {
+    BUILTINS
+        .iter()
+        .find(|b| b.0 == opcode)
+        .map(|&(_, name, arity, write)| (name, arity, write))
+}
+
+// The instruction set with only the built-in opcodes
+pub(super) static BUILTIN_SET: InstructionSet = InstructionSet {
+    custom: BTreeMap::new(),
+    profile: IsaProfile::Day09,
+};
+
+// The revisions of Intcode as the puzzles introduced them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum IsaProfile {
+    // Add, multiply and halt, Position mode only
+    Day02,
+    // Adds input and output, jumps, comparisons and Immediate mode
+    Day05,
+    // Adds the relative base: opcode 9 and Relative mode
+    #[default]
+    Day09,
+}
+
+impl IsaProfile {
+    pub fn allows_opcode(self, opcode: i64) -> bool {
+        match self {
+            IsaProfile::Day02 => matches!(opcode, 1 | 2 | 99),
+            IsaProfile::Day05 => matches!(opcode, 1..=8 | 99),
+            IsaProfile::Day09 => builtin(opcode).is_some(),
+        }
+    }
+
+    pub fn allows_mode(self, mode: Mode) -> bool {
+        match self {
+            IsaProfile::Day02 => mode == Mode::Position,
+            IsaProfile::Day05 => mode != Mode::Relative,
+            IsaProfile::Day09 => true,
+        }
+    }
+}
+
+impl fmt::Display for IsaProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IsaProfile::Day02 => write!(f, "day02"),
+            IsaProfile::Day05 => write!(f, "day05"),
+            IsaProfile::Day09 => write!(f, "day09"),
+        }
+    }
+}
+
+impl std::str::FromStr for IsaProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day02" => Ok(IsaProfile::Day02),
+            "day05" => Ok(IsaProfile::Day05),
+            "day09" => Ok(IsaProfile::Day09),
+            _ => Err(format!("Unknown profile {}, use day02, day05 or day09", s)),
+        }
+    }
+}
+
+// An instruction added to an InstructionSet. The Computer reads every
+// parameter except the one written to, hands the values to execute in
+// parameter order (with 0 for the written one) and applies the Effect.
+pub trait Handler: Send + Sync {
+    fn name(&self) -> &str;
+    // Number of parameters, at most 3
+    fn arity(&self) -> usize;
+    // Index of the parameter the result is written to, if any
+    fn write_param(&self) -> Option<usize> {
+        None
+    }
+    fn execute(&self, args: &[i64]) -> Result<Effect, IntcodeError>;
+}
+
+// What a custom instruction does besides reading its parameters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Effect {
+    // Value to store at the write parameter
+    pub write: Option<i64>,
+    pub output: Option<i64>,
+    // Amount to adjust the relative base by
+    pub relbase: Option<i64>,
+    // Continue here instead of at the next instruction
+    pub jump: Option<i64>,
+    pub halt: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsaError {
+    // The opcode is built in or was registered before
+    OpcodeTaken(i64),
+    // Opcodes are the last two digits of an instruction
+    BadOpcode(i64),
+    // More than 3 parameters, or a write parameter that does not exist
+    BadParams(i64),
+}
+
+impl fmt::Display for IsaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IsaError::OpcodeTaken(n) => write!(f, "Opcode {} is already in use", n),
+            IsaError::BadOpcode(n) => write!(f, "Opcode {} is not between 0 and 99", n),
+            IsaError::BadParams(n) => write!(f, "Opcode {} has impossible parameters", n),
+        }
+    }
+}
+
+impl Error for IsaError {}
+
+// The opcodes a Computer or Debugger understands: the built-in ones of
+// its profile plus any that were registered. Built-in opcodes can not be
+// replaced. Registered opcodes are allowed in every profile, but their
+// parameters are held to its modes.
+#[derive(Clone, Default)]
+pub struct InstructionSet {
+    custom: BTreeMap<i64, Arc<dyn Handler>>,
+    profile: IsaProfile,
+}
+
+impl fmt::Debug for InstructionSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: BTreeMap<_, _> = self.custom.iter().map(|(n, h)| (n, h.name())).collect();
+        write!(f, "InstructionSet {} {:?}", self.profile, names)
+    }
+}
+
+impl InstructionSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Only the built-in opcodes and modes of the given revision
+    pub fn with_profile(profile: IsaProfile) -> Self {
+        Self {
+            profile,
+            ..Default::default()
+        }
+    }
+
+    pub fn profile(&self) -> IsaProfile {
+        self.profile
+    }
+
+    pub fn set_profile(&mut self, profile: IsaProfile) {
+        self.profile = profile;
+    }
+
+    pub fn register<H: Handler + 'static>(
+        &mut self,
+        opcode: i64,
+        handler: H,
+    ) -> Result<(), IsaError> {
+        if !(0..100).contains(&opcode) {
+            return Err(IsaError::BadOpcode(opcode));
+        }
+        if builtin(opcode).is_some() || self.custom.contains_key(&opcode) {
+            return Err(IsaError::OpcodeTaken(opcode));
+        }
+        if handler.arity() > 3 || handler.write_param().is_some_and(|w| w >= handler.arity()) {
+            return Err(IsaError::BadParams(opcode));
+        }
+        self.custom.insert(opcode, Arc::new(handler));
+        Ok(())
+    }
+
+    // Name, arity and write parameter of a built-in opcode in the profile
+    fn profile_builtin(&self, opcode: i64) -> Option<(&'static str, usize, Option<usize>)> {
+        builtin(opcode).filter(|_| self.profile.allows_opcode(opcode))
+    }
+
+    pub fn name(&self, opcode: i64) -> Option<&str> {
+        match self.profile_builtin(opcode) {
+            Some((name, _, _)) => Some(name),
+            None => self.custom.get(&opcode).map(|h| h.name()),
+        }
+    }
+
+    pub fn arity(&self, opcode: i64) -> Option<usize> {
+        match self.profile_builtin(opcode) {
+            Some((_, arity, _)) => Some(arity),
+            None => self.custom.get(&opcode).map(|h| h.arity()),
+        }
+    }
+
+    // Index of the parameter the opcode writes to, if any
+    pub fn write_param(&self, opcode: i64) -> Option<usize> {
+        match self.profile_builtin(opcode) {
+            Some((_, _, write)) => write,
+            None => self.custom.get(&opcode).and_then(|h| h.write_param()),
+        }
+    }
+
+    // Arity of the opcode, or why it can not be executed
+    pub(super) fn check_opcode(&self, opcode: i64) -> Result<usize, IntcodeError> {
+        match self.arity(opcode) {
+            Some(arity) => Ok(arity),
+            None if builtin(opcode).is_some() => {
+                Err(IntcodeError::OpcodeNotInProfile(opcode, self.profile))
+            }
+            None => Err(IntcodeError::UnknownOpcode(opcode)),
+        }
+    }
+
+    pub(super) fn check_mode(&self, mode: Mode) -> Result<(), IntcodeError> {
+        if self.profile.allows_mode(mode) {
+            Ok(())
+        } else {
+            Err(IntcodeError::ModeNotInProfile(mode, self.profile))
+        }
+    }
+
+    pub fn is_custom(&self, opcode: i64) -> bool {
+        self.custom.contains_key(&opcode)
+    }
+}
+
+impl<M: Memory> Computer<M> {
+    // Understand the custom opcodes of the set from now on
+    pub fn set_instruction_set(&mut self, isa: InstructionSet) {
+        self.isa = Some(Arc::new(isa));
+        self.decoded.clear();
+    }
+
+    pub fn instruction_set(&self) -> &InstructionSet {
+        self.isa.as_deref().unwrap_or(&BUILTIN_SET)
+    }
+
+    // Fault on opcodes and modes from later revisions than <profile>
+    pub fn set_isa_profile(&mut self, profile: IsaProfile) {
+        let mut isa = self.instruction_set().clone();
+        isa.set_profile(profile);
+        self.set_instruction_set(isa);
+    }
+
+    // Execute a registered instruction. Everything that can fail is
+    // checked before anything changes.
+    pub(super) fn custom(&mut self, ins: &Instruction) -> Result<(), IntcodeError> {
+        let handler = self
+            .isa
+            .as_ref()
+            .and_then(|isa| isa.custom.get(&ins.opcode))
+            .cloned()
+            .ok_or(IntcodeError::UnknownOpcode(ins.opcode))?;
+        let mut args = [0; 3];
+        for (idx, arg) in args.iter_mut().enumerate().take(ins.arity) {
+            if ins.write != Some(idx) {
+                *arg = self.load(ins, idx)?;
+            }
+        }
+        let effect = handler.execute(&args[..ins.arity])?;
+        let write = match (ins.write, effect.write) {
+            (Some(idx), Some(value)) => Some((self.destination(ins, idx)?, value)),
+            _ => None,
+        };
+        let relbase = self.relative(effect.relbase.unwrap_or(0))?;
+        if effect.output.is_some() && self.budget.is_some() {
+            self.check_output()?;
+        }
+        if let Some((address, value)) = write {
+            self.store(address, value);
+        }
+        if let Some(value) = effect.output {
+            self.emit(value);
+            self.charge_output();
+        }
+        self.relbse = relbase;
+        if effect.halt {
+            self.halted = true;
+        }
+        match effect.jump {
+            Some(target) => self.procnt = target,
+            None if !effect.halt => self.procnt += ins.length() as i64,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::TextLog;
+    use crate::intcode_asm::Debugger;
+    use std::sync::Mutex;
+
+    // mod a, b, c: c := a % b, failing on division by zero
+    struct Modulo;
+
+    impl Handler for Modulo {
+        fn name(&self) -> &str {
+            "mod"
+        }
+
+        fn arity(&self) -> usize {
+            3
+        }
+
+        fn write_param(&self) -> Option<usize> {
+            Some(2)
+        }
+
+        fn execute(&self, args: &[i64]) -> Result<Effect, IntcodeError> {
+            if args[1] == 0 {
+                return Err(IntcodeError::Custom("Division by zero"));
+            }
+            Ok(Effect {
+                write: Some(args[0] % args[1]),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[test]
+    fn test_custom_opcode() {
+        let mut isa = InstructionSet::new();
+        assert_eq!(isa.register(1, Modulo), Err(IsaError::OpcodeTaken(1)));
+        assert_eq!(isa.register(100, Modulo), Err(IsaError::BadOpcode(100)));
+        assert_eq!(isa.register(42, Modulo), Ok(()));
+        assert_eq!(isa.name(42), Some("mod"));
+        assert_eq!(isa.arity(42), Some(3));
+
+        // Outputs 17 % <input>
+        let program = vec![3, 9, 142, 17, 9, 9, 4, 9, 99, 0];
+        let mut computer = Computer::new(program.clone(), vec![5]);
+        assert_eq!(
+            computer.try_run().unwrap_err().error,
+            IntcodeError::UnknownOpcode(42)
+        );
+        let mut computer = Computer::new(program.clone(), vec![5]);
+        computer.set_instruction_set(isa.clone());
+        computer.start_recording();
+        assert_eq!(computer.run(), &vec![2]);
+        assert!(computer.run_back_to(2));
+        assert_eq!(computer.memory()[9], 5);
+
+        let log = Arc::new(Mutex::new(TextLog::new(Vec::new())));
+        let mut computer = Computer::new(program.clone(), vec![5]);
+        computer.set_instruction_set(isa.clone());
+        computer.trace_to(log.clone());
+        computer.run();
+        drop(computer);
+        let log = Arc::try_unwrap(log).ok().unwrap().into_inner().unwrap();
+        let log = String::from_utf8(log.into_inner().unwrap()).unwrap();
+        assert!(log.contains("   2: mod 17 *9=5 *9 ; *9 := 2\n"));
+
+        let listing = Debugger::with_instruction_set(program, isa).assembly();
+        assert!(listing.contains("   2: *9 := mod(17, *9)\n   6: output(*9)\n"));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let error = |program: Vec<i64>, profile| {
+            let mut computer = Computer::new(program, vec![1]);
+            computer.set_isa_profile(profile);
+            computer.try_run().err().map(|fault| fault.error)
+        };
+        assert_eq!(error(vec![1, 0, 0, 0, 99], IsaProfile::Day02), None);
+        assert_eq!(
+            error(vec![1101, 1, 2, 0, 99], IsaProfile::Day02),
+            Some(IntcodeError::ModeNotInProfile(
+                Mode::Immediate,
+                IsaProfile::Day02
+            ))
+        );
+        assert_eq!(
+            error(vec![3, 0, 99], IsaProfile::Day02),
+            Some(IntcodeError::OpcodeNotInProfile(3, IsaProfile::Day02))
+        );
+        assert_eq!(
+            error(vec![204, 0, 99], IsaProfile::Day05),
+            Some(IntcodeError::ModeNotInProfile(
+                Mode::Relative,
+                IsaProfile::Day05
+            ))
+        );
+        assert_eq!(error(vec![109, 1, 204, -1, 99], IsaProfile::Day09), None);
+        assert_eq!(
+            IntcodeError::OpcodeNotInProfile(9, IsaProfile::Day05).to_string(),
+            "Opcode 9 is not in the day05 instruction set"
+        );
+
+        let mut debugger = Debugger::from(vec![1, 0, 0, 0, 1101, 1, 2, 0, 99]);
+        debugger.set_isa_profile(IsaProfile::Day02);
+        assert!(debugger
+            .assembly()
+            .starts_with("   0: *0 := *0 + *0\n   4:    1101 "));
+    }
+}
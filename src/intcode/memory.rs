@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fmt;
+
+// Where a Computer keeps its memory. Addresses that were never written
+// read as zero. Vec<i64> is the fastest choice and the default; it grows
+// up to the highest address written. PagedMemory only allocates the
+// parts that are actually used.
+pub trait Memory: From<Vec<i64>> + Clone + fmt::Debug + Default + Send + 'static {
+    fn peek(&self, address: usize) -> i64;
+    fn poke(&mut self, address: usize, value: i64);
+    // One past the highest address that was loaded or written
+    fn len(&self) -> usize;
+    // Forget everything from address <len> on
+    fn truncate(&mut self, len: usize);
+    // The whole memory up to len()
+    fn to_vec(&self) -> Vec<i64>;
+
+    // The memory up to len() as runs of consecutive values, each with the
+    // address of its first value. Addresses between runs hold zero.
+    fn runs(&self) -> Vec<(usize, Vec<i64>)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        vec![(0, self.to_vec())]
+    }
+
+    // Addresses from here on are a fault when no limit was set with
+    // set_address_limit, so that a stray write can not use up all memory
+    const ADDRESS_LIMIT: Option<usize> = None;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Memory for Vec<i64> {
+    // 128 MiB, far more than any puzzle uses
+    const ADDRESS_LIMIT: Option<usize> = Some(1 << 24);
+
+    #[inline]
+    fn peek(&self, address: usize) -> i64 {
+        *self.as_slice().get(address).unwrap_or(&0)
+    }
+
+    #[inline]
+    fn poke(&mut self, address: usize, value: i64) {
+        if self.len() <= address {
+            self.resize(address + 1, 0);
+        }
+        self[address] = value;
+    }
+
+    fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len)
+    }
+
+    fn to_vec(&self) -> Vec<i64> {
+        self.clone()
+    }
+}
+
+const PAGE_SIZE: usize = 1024;
+
+// Memory in pages of 1024 values that are allocated on first write, so
+// that a program can use addresses far apart without needing memory for
+// everything in between.
+#[derive(Debug, Clone, Default)]
+pub struct PagedMemory {
+    pages: HashMap<usize, Box<[i64; PAGE_SIZE]>>,
+    len: usize,
+}
+
+impl PagedMemory {
+    // Number of pages in use
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl From<Vec<i64>> for PagedMemory {
+    fn from(values: Vec<i64>) -> Self {
+        let mut memory = PagedMemory::default();
+        for (address, value) in values.into_iter().enumerate() {
+            memory.poke(address, value);
+        }
+        memory
+    }
+}
+
+impl Memory for PagedMemory {
+    fn peek(&self, address: usize) -> i64 {
+        self.pages
+            .get(&(address / PAGE_SIZE))
+            .map_or(0, |page| page[address % PAGE_SIZE])
+    }
+
+    fn poke(&mut self, address: usize, value: i64) {
+        let page = self
+            .pages
+            .entry(address / PAGE_SIZE)
+            .or_insert_with(|| Box::new([0; PAGE_SIZE]));
+        page[address % PAGE_SIZE] = value;
+        self.len = self.len.max(address + 1);
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        self.pages.retain(|&number, _| number * PAGE_SIZE < len);
+        if let Some(page) = self.pages.get_mut(&(len / PAGE_SIZE)) {
+            for value in &mut page[len % PAGE_SIZE..] {
+                *value = 0;
+            }
+        }
+        self.len = len;
+    }
+
+    fn to_vec(&self) -> Vec<i64> {
+        (0..self.len).map(|address| self.peek(address)).collect()
+    }
+
+    // One run per stretch of pages in use, without the zeros at either
+    // end. The last run still ends at len().
+    fn runs(&self) -> Vec<(usize, Vec<i64>)> {
+        let mut numbers: Vec<usize> = self.pages.keys().copied().collect();
+        numbers.sort_unstable();
+        let mut runs: Vec<(usize, Vec<i64>)> = Vec::new();
+        for number in numbers {
+            let start = number * PAGE_SIZE;
+            let end = (start + PAGE_SIZE).min(self.len);
+            if start >= end {
+                continue;
+            }
+            let values = &self.pages[&number][..end - start];
+            match runs.last_mut() {
+                Some((first, run)) if *first + run.len() == start => run.extend_from_slice(values),
+                _ => runs.push((start, values.to_vec())),
+            }
+        }
+        let last = runs.len().saturating_sub(1);
+        for (idx, (start, run)) in runs.iter_mut().enumerate() {
+            let keep = if idx == last { 1 } else { 0 };
+            let zeros = run
+                .iter()
+                .take(run.len() - keep)
+                .take_while(|&&v| v == 0)
+                .count();
+            run.drain(..zeros);
+            *start += zeros;
+            if idx != last {
+                while run.last() == Some(&0) {
+                    run.pop();
+                }
+            }
+        }
+        runs.retain(|(_, run)| !run.is_empty());
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Computer, IntcodeError};
+
+    #[test]
+    fn test_paged_memory() {
+        let mut memory = PagedMemory::from(vec![1, 2, 3]);
+        memory.poke(5_000_000_000, 7);
+        assert_eq!(memory.pages(), 2);
+        assert_eq!(memory.len(), 5_000_000_001);
+        assert_eq!(memory.peek(5_000_000_000), 7);
+        assert_eq!(memory.peek(4_999_999_999), 0);
+        memory.poke(2000, 9);
+        memory.truncate(2000);
+        assert_eq!(memory.pages(), 2);
+        assert_eq!(memory.peek(2000), 0);
+        memory.truncate(2);
+        assert_eq!(memory.pages(), 1);
+        assert_eq!(memory.to_vec(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_computer_with_pages() {
+        // Store 42 far away, read it back and output it
+        let program = vec![1101, 40, 2, 1 << 40, 4, 1 << 40, 99];
+        let mut computer = Computer::with_memory(PagedMemory::from(program.clone()));
+        assert_eq!(computer.resume(), crate::intcode::StopReason::Output(42));
+
+        let mut computer = Computer::from(program.clone());
+        assert_eq!(
+            computer.try_run().unwrap_err().error,
+            IntcodeError::AddressTooHigh(1 << 40)
+        );
+        let mut computer = Computer::from(program);
+        computer.set_address_limit(1 << 20);
+        assert_eq!(
+            computer.try_run().unwrap_err().error,
+            IntcodeError::AddressTooHigh(1 << 40)
+        );
+    }
+}
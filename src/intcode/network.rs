@@ -0,0 +1,431 @@
+use super::{Computer, Fault, StopReason};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// A packet sent from one address to another. The data are the values
+// that follow the destination address in the sender's output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub from: i64,
+    pub to: i64,
+    pub data: Vec<i64>,
+}
+
+impl fmt::Display for Packet {
+    // For example: `3 -> 255: 17, 42`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data: Vec<String> = self.data.iter().map(|n| n.to_string()).collect();
+        write!(f, "{} -> {}: {}", self.from, self.to, data.join(", "))
+    }
+}
+
+// What the network should do after asking the router
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action<T> {
+    Continue,
+    // Put this packet in the queue of the node it is addressed to
+    Deliver(Packet),
+    // Stop running and return this from Network::run
+    Stop(T),
+}
+
+// Decides what happens to packets that are not addressed to a node, and
+// what to do when the network is idle.
+pub trait Router {
+    type Output;
+    fn route(&mut self, packet: &Packet) -> Action<Self::Output>;
+    fn idle(&mut self) -> Action<Self::Output>;
+}
+
+// Stops at the first packet that leaves the network
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FirstPacket;
+
+impl Router for FirstPacket {
+    type Output = Packet;
+
+    fn route(&mut self, packet: &Packet) -> Action<Packet> {
+        Action::Stop(packet.clone())
+    }
+
+    fn idle(&mut self) -> Action<Packet> {
+        Action::Continue
+    }
+}
+
+// Keeps the last packet sent to its address, and sends it on to node 0
+// whenever the network is idle. Stops when it would send the same Y value
+// (the last one of the data) twice in a row, returning that packet.
+// Packets to other addresses outside the network are dropped.
+#[derive(Debug, Clone)]
+pub struct Nat {
+    address: i64,
+    last: Option<Packet>,
+    // The Y value of the packet it sent last
+    delivered: Option<i64>,
+}
+
+impl Nat {
+    pub fn new(address: i64) -> Self {
+        Self {
+            address,
+            last: None,
+            delivered: None,
+        }
+    }
+}
+
+impl Router for Nat {
+    type Output = Packet;
+
+    fn route(&mut self, packet: &Packet) -> Action<Packet> {
+        if packet.to == self.address {
+            self.last = Some(packet.clone());
+        }
+        Action::Continue
+    }
+
+    fn idle(&mut self) -> Action<Packet> {
+        let packet = match &self.last {
+            Some(packet) => packet,
+            None => return Action::Continue,
+        };
+        let y = packet.data.last().copied();
+        if y.is_some() && self.delivered == y {
+            return Action::Stop(packet.clone());
+        }
+        self.delivered = y;
+        Action::Deliver(Packet {
+            from: self.address,
+            to: 0,
+            data: packet.data.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkError {
+    // A node faulted
+    Fault { node: usize, fault: Fault },
+    // The router delivered a packet to an address that is not a node
+    NoSuchNode(i64),
+    // Every node has halted, so nothing will ever happen again
+    AllHalted,
+    // A node reached a limit set with set_limits, or a breakpoint
+    Stopped { node: usize, reason: StopReason },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Fault { node, fault } => write!(f, "Node {}: {}", node, fault),
+            NetworkError::NoSuchNode(a) => write!(f, "No node at address {}", a),
+            NetworkError::AllHalted => write!(f, "Every node has halted"),
+            NetworkError::Stopped { node, reason } => {
+                write!(f, "Node {} stopped: {:?}", node, reason)
+            }
+        }
+    }
+}
+
+impl Error for NetworkError {}
+
+#[derive(Debug, Clone)]
+struct Node {
+    computer: Computer,
+    queue: VecDeque<Packet>,
+    // Output values of a packet that is not complete yet
+    partial: Vec<i64>,
+    // Times the node asked for input and got none, since it last did
+    // anything else
+    empty_polls: usize,
+}
+
+// A network of Computers running the same program, each with its own
+// address. A node learns its address from its first input. Afterwards it
+// receives the data of the packets sent to it, or -1 if there are none.
+// It sends a packet by outputting the destination address followed by
+// the data.
+#[derive(Debug, Clone)]
+pub struct Network {
+    nodes: Vec<Node>,
+    packet_len: usize,
+    idle_polls: usize,
+    log: Option<Vec<Packet>>,
+}
+
+impl Network {
+    // Start <count> nodes, with addresses 0 up to count. Packets have two
+    // values of data.
+    pub fn new(program: &[i64], count: usize) -> Self {
+        let nodes = (0..count)
+            .map(|address| Node {
+                computer: Computer::new(program.to_vec(), vec![address as i64]),
+                queue: VecDeque::new(),
+                partial: Vec::new(),
+                empty_polls: 0,
+            })
+            .collect();
+        Self {
+            nodes,
+            packet_len: 2,
+            idle_polls: 2,
+            log: None,
+        }
+    }
+
+    // Number of data values in a packet
+    pub fn set_packet_len(&mut self, len: usize) {
+        self.packet_len = len;
+    }
+
+    // How many times in a row a node must ask for input and get none
+    // before it counts as idle
+    pub fn set_idle_polls(&mut self, polls: usize) {
+        self.idle_polls = polls.max(1);
+    }
+
+    pub fn node(&self, address: usize) -> &Computer {
+        &self.nodes[address].computer
+    }
+
+    // For setting limits and breakpoints on a node
+    pub fn node_mut(&mut self, address: usize) -> &mut Computer {
+        &mut self.nodes[address].computer
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Keep every packet sent from now on, including those from the router
+    pub fn start_log(&mut self) {
+        self.log.get_or_insert_with(Vec::new);
+    }
+
+    // The packets sent since start_log, in order
+    pub fn log(&self) -> &[Packet] {
+        self.log.as_deref().unwrap_or(&[])
+    }
+
+    // Write the log, one packet per line
+    pub fn write_log<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for packet in self.log() {
+            writeln!(writer, "{}", packet)?;
+        }
+        writer.flush()
+    }
+
+    pub fn save_log<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_log(BufWriter::new(File::create(path)?))
+    }
+
+    // Put a packet in the queue of the node it is addressed to
+    pub fn send(&mut self, packet: Packet) -> Result<(), NetworkError> {
+        let node = usize::try_from(packet.to)
+            .ok()
+            .and_then(|to| self.nodes.get_mut(to))
+            .ok_or(NetworkError::NoSuchNode(packet.to))?;
+        node.queue.push_back(packet.clone());
+        if let Some(log) = &mut self.log {
+            log.push(packet);
+        }
+        Ok(())
+    }
+
+    // True if no packets are waiting and every node that has not halted
+    // keeps asking for input
+    pub fn is_idle(&self) -> bool {
+        self.nodes.iter().all(|node| {
+            node.computer.is_halted()
+                || (node.queue.is_empty() && node.empty_polls >= self.idle_polls)
+        })
+    }
+
+    // Run every node in turn until it needs input or produces a value,
+    // and pass the packets they send to the nodes or to the router, until
+    // the router stops.
+    pub fn run<R: Router>(&mut self, router: &mut R) -> Result<R::Output, NetworkError> {
+        loop {
+            if self.nodes.iter().all(|node| node.computer.is_halted()) {
+                return Err(NetworkError::AllHalted);
+            }
+            for address in 0..self.nodes.len() {
+                let packet = match self.step(address)? {
+                    Some(packet) => packet,
+                    None => continue,
+                };
+                if (0..self.nodes.len() as i64).contains(&packet.to) {
+                    self.send(packet)?;
+                    continue;
+                }
+                if let Some(log) = &mut self.log {
+                    log.push(packet.clone());
+                }
+                if let Some(output) = self.act(router.route(&packet))? {
+                    return Ok(output);
+                }
+            }
+            if self.is_idle() {
+                if let Some(output) = self.act(router.idle())? {
+                    return Ok(output);
+                }
+            }
+        }
+    }
+
+    fn act<T>(&mut self, action: Action<T>) -> Result<Option<T>, NetworkError> {
+        match action {
+            Action::Continue => Ok(None),
+            Action::Deliver(packet) => self.send(packet).map(|_| None),
+            Action::Stop(output) => Ok(Some(output)),
+        }
+    }
+
+    // Run one node until it needs input or produces a value. Returns a
+    // packet once all of its values have been produced.
+    fn step(&mut self, address: usize) -> Result<Option<Packet>, NetworkError> {
+        let packet_len = self.packet_len;
+        let node = &mut self.nodes[address];
+        let fault = |fault| NetworkError::Fault {
+            node: address,
+            fault,
+        };
+        match node.computer.try_resume().map_err(fault)? {
+            StopReason::NeedsInput => match node.queue.pop_front() {
+                Some(packet) => {
+                    for value in packet.data {
+                        node.computer.more_input(value);
+                    }
+                    node.empty_polls = 0;
+                }
+                None => {
+                    node.computer.more_input(-1);
+                    node.empty_polls += 1;
+                }
+            },
+            StopReason::Output(value) => {
+                node.partial.push(value);
+                node.empty_polls = 0;
+            }
+            StopReason::Halted => {}
+            reason => {
+                return Err(NetworkError::Stopped {
+                    node: address,
+                    reason,
+                })
+            }
+        }
+        if node.partial.len() <= packet_len {
+            return Ok(None);
+        }
+        let mut values = node.partial.drain(..);
+        let to = values.next().unwrap();
+        Ok(Some(Packet {
+            from: address as i64,
+            to,
+            data: values.collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Limit, Limits};
+
+    // Reads (x, y) packets and sends (x, y + 1) on to the next address
+    const RELAY: [i64; 31] = [
+        3, 100, 3, 101, 1008, 101, -1, 102, 1005, 102, 2, 3, 103, 1001, 100, 1, 104, 4, 104, 4,
+        101, 1001, 103, 1, 103, 4, 103, 1105, 1, 2, 99,
+    ];
+
+    fn start(packet: Packet) -> Network {
+        let mut network = Network::new(&RELAY, 2);
+        network.start_log();
+        network.send(packet).unwrap();
+        network
+    }
+
+    #[test]
+    fn test_first_packet() {
+        let mut network = start(Packet {
+            from: -1,
+            to: 0,
+            data: vec![5, 7],
+        });
+        let packet = network.run(&mut FirstPacket).unwrap();
+        assert_eq!(packet.data, vec![5, 9]);
+        let mut log = Vec::new();
+        network.write_log(&mut log).unwrap();
+        assert_eq!(
+            String::from_utf8(log).unwrap(),
+            "-1 -> 0: 5, 7\n0 -> 1: 5, 8\n1 -> 2: 5, 9\n"
+        );
+    }
+
+    #[test]
+    fn test_nat() {
+        // Without the increment, the NAT sees the same packet twice
+        let mut program = RELAY.to_vec();
+        program[23] = 0;
+        let mut network = Network::new(&program, 2);
+        network
+            .send(Packet {
+                from: -1,
+                to: 1,
+                data: vec![3, 4],
+            })
+            .unwrap();
+        network.start_log();
+        let packet = network.run(&mut Nat::new(2)).unwrap();
+        assert_eq!(packet.data, vec![3, 4]);
+        assert_eq!(network.log().len(), 4);
+        assert_eq!(network.log()[1].from, 2);
+
+        // Only Y has to repeat
+        let mut nat = Nat::new(255);
+        let packet = |x| Packet {
+            from: 1,
+            to: 255,
+            data: vec![x, 4],
+        };
+        nat.route(&packet(1));
+        assert!(matches!(nat.idle(), Action::Deliver(_)));
+        nat.route(&packet(2));
+        assert!(matches!(nat.idle(), Action::Stop(p) if p == packet(2)));
+
+        assert_eq!(
+            Network::new(&[99], 3).run(&mut Nat::new(2)),
+            Err(NetworkError::AllHalted)
+        );
+    }
+
+    #[test]
+    fn test_stopped() {
+        // A node that loops forever stops the network at its limit
+        let mut network = Network::new(&[1105, 1, 0], 2);
+        for address in 0..2 {
+            network.node_mut(address).set_limits(Limits {
+                instructions: Some(100),
+                ..Default::default()
+            });
+        }
+        assert_eq!(
+            network.run(&mut FirstPacket),
+            Err(NetworkError::Stopped {
+                node: 0,
+                reason: StopReason::Limit(Limit::Instructions)
+            })
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use super::{Computer, Fault, StopReason};
+use std::error::Error;
+use std::fmt;
+
+// Names a node of a Pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "node {}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineError {
+    Fault { node: NodeId, fault: Fault },
+    // Every node that has not halted is waiting for input that will never
+    // come. Lists those nodes.
+    Deadlock(Vec<NodeId>),
+    // The node halted before producing the value we were waiting for
+    Halted(NodeId),
+    // The node reached a limit set with set_limits, or a breakpoint
+    Stopped { node: NodeId, reason: StopReason },
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Fault { node, fault } => write!(f, "{}: {}", node, fault),
+            PipelineError::Deadlock(blocked) => {
+                let names: Vec<String> = blocked.iter().map(|id| id.to_string()).collect();
+                write!(f, "Deadlock, waiting for input: {}", names.join(", "))
+            }
+            PipelineError::Halted(node) => write!(f, "{} halted", node),
+            PipelineError::Stopped { node, reason } => write!(f, "{} stopped: {:?}", node, reason),
+        }
+    }
+}
+
+impl Error for PipelineError {}
+
+enum Until {
+    Halts(NodeId),
+    Emits(NodeId),
+}
+
+// A graph of Computers, where the output of a node is sent as input to
+// every node it is connected to. Any shape works, including one output
+// going to several nodes and loops back to earlier nodes.
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    computers: Vec<Computer>,
+    targets: Vec<Vec<NodeId>>,
+    outputs: Vec<Vec<i64>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Add a node that reads the given input before anything that is sent
+    // to it.
+    pub fn add_node(&mut self, mut computer: Computer, input: Vec<i64>) -> NodeId {
+        for value in input {
+            computer.more_input(value);
+        }
+        self.computers.push(computer);
+        self.targets.push(Vec::new());
+        self.outputs.push(Vec::new());
+        NodeId(self.computers.len() - 1)
+    }
+
+    // Send all output of one node to another as well
+    pub fn connect(&mut self, from: NodeId, to: NodeId) {
+        self.targets[from.0].push(to);
+    }
+
+    // Give a node more input
+    pub fn send(&mut self, node: NodeId, value: i64) {
+        self.computers[node.0].more_input(value);
+    }
+
+    pub fn computer(&self, node: NodeId) -> &Computer {
+        &self.computers[node.0]
+    }
+
+    // Everything the node has produced so far
+    pub fn outputs(&self, node: NodeId) -> &[i64] {
+        &self.outputs[node.0]
+    }
+
+    pub fn run_until_halt(&mut self, node: NodeId) -> Result<(), PipelineError> {
+        self.run(Until::Halts(node)).map(|_| ())
+    }
+
+    // Run until the node produces a value, and return the value
+    pub fn run_until_output(&mut self, node: NodeId) -> Result<i64, PipelineError> {
+        self.run(Until::Emits(node)).map(Option::unwrap)
+    }
+
+    // Run every node in turn until it waits for input, passing its output
+    // on, until the condition is met. When a whole round passes without
+    // output or halts, nothing can change any more.
+    fn run(&mut self, until: Until) -> Result<Option<i64>, PipelineError> {
+        loop {
+            match until {
+                Until::Halts(node) if self.computers[node.0].is_halted() => return Ok(None),
+                Until::Emits(node) if self.computers[node.0].is_halted() => {
+                    return Err(PipelineError::Halted(node))
+                }
+                _ => {}
+            }
+            let mut progress = false;
+            for idx in 0..self.computers.len() {
+                let node = NodeId(idx);
+                while !self.computers[idx].is_halted() {
+                    let fault = |fault| PipelineError::Fault { node, fault };
+                    match self.computers[idx].try_resume().map_err(fault)? {
+                        StopReason::Output(value) => {
+                            progress = true;
+                            self.outputs[idx].push(value);
+                            for target in &self.targets[idx] {
+                                self.computers[target.0].more_input(value);
+                            }
+                            if let Until::Emits(n) = until {
+                                if n == node {
+                                    return Ok(Some(value));
+                                }
+                            }
+                        }
+                        // Checked at the start of the next round
+                        StopReason::Halted => progress = true,
+                        StopReason::NeedsInput => break,
+                        reason => return Err(PipelineError::Stopped { node, reason }),
+                    }
+                }
+            }
+            if !progress {
+                let blocked = (0..self.computers.len())
+                    .filter(|&idx| !self.computers[idx].is_halted())
+                    .map(NodeId)
+                    .collect();
+                return Err(PipelineError::Deadlock(blocked));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Limit, Limits};
+
+    // Doubles every input
+    fn doubler() -> Computer {
+        Computer::from(vec![3, 9, 102, 2, 9, 9, 4, 9, 1105, 1, 0])
+    }
+
+    #[test]
+    fn test_fan_out() {
+        let mut pipeline = Pipeline::new();
+        let source = pipeline.add_node(doubler(), vec![1, 2]);
+        let left = pipeline.add_node(doubler(), vec![]);
+        let right = pipeline.add_node(doubler(), vec![100]);
+        pipeline.connect(source, left);
+        pipeline.connect(source, right);
+        assert_eq!(pipeline.run_until_output(right), Ok(200));
+        assert_eq!(pipeline.run_until_output(right), Ok(4));
+        assert_eq!(pipeline.outputs(left), &[4, 8]);
+        assert_eq!(
+            pipeline.run_until_halt(left),
+            Err(PipelineError::Deadlock(vec![source, left, right]))
+        );
+    }
+
+    #[test]
+    fn test_cycle() {
+        // Reads a number and outputs it plus one, until the output reaches
+        // 10
+        let counter = || {
+            Computer::from(vec![
+                3, 100, 1001, 100, 1, 100, 4, 100, 1007, 100, 10, 101, 1005, 101, 0, 99,
+            ])
+        };
+        let mut pipeline = Pipeline::new();
+        let first = pipeline.add_node(counter(), vec![0]);
+        let second = pipeline.add_node(counter(), vec![]);
+        pipeline.connect(first, second);
+        pipeline.connect(second, first);
+        assert_eq!(pipeline.run_until_halt(second), Ok(()));
+        assert_eq!(pipeline.outputs(first), &[1, 3, 5, 7, 9]);
+        assert_eq!(pipeline.outputs(second), &[2, 4, 6, 8, 10]);
+        // The last value of the second one is still waiting for the first
+        assert_eq!(pipeline.run_until_output(first), Ok(11));
+        assert_eq!(
+            pipeline.run_until_output(first),
+            Err(PipelineError::Halted(first))
+        );
+    }
+
+    #[test]
+    fn test_limit() {
+        // A node that loops forever stops the pipeline at its limit
+        let mut spinner = Computer::from(vec![1105, 1, 0]);
+        spinner.set_limits(Limits {
+            instructions: Some(100),
+            ..Default::default()
+        });
+        let mut pipeline = Pipeline::new();
+        let node = pipeline.add_node(spinner, vec![]);
+        assert_eq!(
+            pipeline.run_until_halt(node),
+            Err(PipelineError::Stopped {
+                node,
+                reason: StopReason::Limit(Limit::Instructions)
+            })
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use super::{opcode_name, Computer, Instruction, Memory, Mode};
+use crate::intcode_aot::find_code;
+use crate::intcode_asm::Debugger;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Hot spots shown in a report
+const HOT_SPOTS: usize = 10;
+
+// Execution counts gathered while profiling
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    // Executions of the instruction at each address, keyed by address so
+    // that code far into paged memory costs no more than code at the start
+    addresses: BTreeMap<usize, u64>,
+    opcodes: BTreeMap<i64, u64>,
+    // Parameters used in each mode: position, immediate, relative
+    modes: [u64; 3],
+    // Instructions since the last input or output
+    since_io: u64,
+    // Instructions between one input or output and the next
+    io_gaps: Vec<u64>,
+}
+
+impl<M: Memory> Computer<M> {
+    // Count every instruction executed from now on. Starts over if the
+    // computer was already profiling.
+    pub fn start_profiling(&mut self) {
+        self.profile = Some(Box::new(Profile::default()));
+    }
+
+    // Stop counting and hand over what was counted
+    pub fn stop_profiling(&mut self) -> Option<Profile> {
+        self.profile.take().map(|profile| *profile)
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    // Called after every instruction that was executed at <procnt>
+    pub(super) fn record(&mut self, procnt: i64, ins: &Instruction) {
+        if let Some(profile) = &mut self.profile {
+            profile.record(procnt as usize, ins);
+        }
+    }
+}
+
+impl Profile {
+    fn record(&mut self, address: usize, ins: &Instruction) {
+        *self.addresses.entry(address).or_default() += 1;
+        *self.opcodes.entry(ins.opcode).or_default() += 1;
+        for mode in &ins.modes[..ins.length() - 1] {
+            self.modes[mode_index(*mode)] += 1;
+        }
+        if ins.opcode == 3 || ins.opcode == 4 {
+            self.io_gaps.push(self.since_io);
+            self.since_io = 0;
+        } else {
+            self.since_io += 1;
+        }
+    }
+
+    // Total number of instructions executed
+    pub fn instructions(&self) -> u64 {
+        self.opcodes.values().sum()
+    }
+
+    // Executions of the instruction at <address>
+    pub fn count(&self, address: usize) -> u64 {
+        *self.addresses.get(&address).unwrap_or(&0)
+    }
+
+    pub fn opcode_count(&self, opcode: i64) -> u64 {
+        *self.opcodes.get(&opcode).unwrap_or(&0)
+    }
+
+    // Number of parameters used in the given mode
+    pub fn mode_count(&self, mode: Mode) -> u64 {
+        self.modes[mode_index(mode)]
+    }
+
+    // For every input or output instruction, the number of instructions
+    // executed since the one before it (or since profiling started)
+    pub fn io_gaps(&self) -> &[u64] {
+        &self.io_gaps
+    }
+
+    // The <count> addresses that executed most often, busiest first
+    pub fn hot_spots(&self, count: usize) -> Vec<(usize, u64)> {
+        let mut spots: Vec<(usize, u64)> = self.addresses.iter().map(|(&a, &n)| (a, n)).collect();
+        spots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        spots.truncate(count);
+        spots
+    }
+
+    // A summary of the counts, followed by the listing of the code of
+    // <program> with the execution count of every instruction in front of
+    // it.
+    pub fn report(&self, program: &[i64]) -> String {
+        let total = self.instructions();
+        let percent = |n: u64| 100.0 * n as f64 / total.max(1) as f64;
+        let mut result = format!("Instructions executed: {}\n\nOpcodes:\n", total);
+        for (&opcode, &n) in &self.opcodes {
+            result += &format!("  {:5} {:12} {:5.1}%\n", opcode_name(opcode), n, percent(n));
+        }
+        result += "\nParameter modes:\n";
+        for (name, n) in ["position", "immediate", "relative"]
+            .iter()
+            .zip(&self.modes)
+        {
+            result += &format!("  {:10} {:12}\n", name, n);
+        }
+        if let (Some(min), Some(max)) = (self.io_gaps.iter().min(), self.io_gaps.iter().max()) {
+            let mean = self.io_gaps.iter().sum::<u64>() as f64 / self.io_gaps.len() as f64;
+            result += &format!(
+                "\nInstructions between I/O: min {}, mean {:.1}, max {} ({} I/O)\n",
+                min,
+                mean,
+                max,
+                self.io_gaps.len()
+            );
+        }
+        result += "\nHot spots:\n";
+        let mut debugger = Debugger::from(program.to_vec());
+        for (address, n) in self.hot_spots(HOT_SPOTS) {
+            result += &format!("{:12} {:5.1}% ", n, percent(n));
+            result += &debugger.instruction(address);
+        }
+        result += "\nListing:\n";
+        // The code reachable without running it, and whatever else ran
+        let code = find_code(program);
+        let reachable = (0..code.len()).filter(|&address| code[address].is_some());
+        let code: BTreeSet<usize> = reachable.chain(self.addresses.keys().copied()).collect();
+        result += &debugger.annotated_listing(&code, |address| match self.count(address) {
+            0 => format!("{:12} ", ""),
+            n => format!("{:12} ", n),
+        });
+        result
+    }
+}
+
+fn mode_index(mode: Mode) -> usize {
+    match mode {
+        Mode::Position => 0,
+        Mode::Immediate => 1,
+        Mode::Relative => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::PagedMemory;
+
+    #[test]
+    fn test_profile() {
+        // Count down from the input, outputting every number
+        let program = vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0];
+        let mut computer = Computer::new(program.clone(), vec![3]);
+        computer.start_profiling();
+        computer.run();
+        let profile = computer.stop_profiling().unwrap();
+        assert!(computer.profile().is_none());
+        assert_eq!(profile.instructions(), 11);
+        assert_eq!(profile.count(0), 1);
+        assert_eq!(profile.count(2), 3);
+        assert_eq!(profile.count(12), 0);
+        assert_eq!(profile.opcode_count(1), 3);
+        assert_eq!(profile.opcode_count(99), 1);
+        assert_eq!(profile.mode_count(Mode::Immediate), 6);
+        assert_eq!(profile.mode_count(Mode::Position), 13);
+        assert_eq!(profile.io_gaps(), &[0, 0, 2, 2]);
+        assert_eq!(profile.hot_spots(2), vec![(2, 3), (4, 3)]);
+
+        let report = profile.report(&program);
+        assert!(report.contains("Instructions executed: 11\n"));
+        assert!(report.contains("min 0, mean 1.0, max 2 (4 I/O)"));
+        assert!(report.contains("           3    2: output(*12)\n"));
+        assert!(report.contains("           1   11: halt\n"));
+        assert!(!report.contains("data"));
+
+        // A comparison that disassembles fused with its jump is still one
+        // line per instruction
+        let program = vec![1008, 9, 0, 10, 1005, 10, 0, 104, 7, 99, 0];
+        let mut computer = Computer::new(program.clone(), vec![]);
+        computer.start_profiling();
+        computer.run();
+        let report = computer.stop_profiling().unwrap().report(&program);
+        assert!(report.contains("25.0%    0: *10 := *9 == 0\n           1"));
+        assert!(report.contains("           1    4: if *10 != 0 { goto 0 }\n"));
+    }
+
+    #[test]
+    fn test_far_code() {
+        // Writes a halt far into paged memory and jumps there
+        let program = vec![1101, 99, 0, 1 << 40, 1105, 1, 1 << 40];
+        let mut computer = Computer::with_memory(PagedMemory::from(program));
+        computer.start_profiling();
+        computer.run();
+        let profile = computer.stop_profiling().unwrap();
+        assert_eq!(profile.count(1 << 40), 1);
+    }
+}
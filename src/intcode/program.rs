@@ -0,0 +1,192 @@
+use super::Computer;
+use std::borrow::Borrow;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+use std::str::FromStr;
+
+// An Intcode program: the initial memory of a Computer. In text form the
+// values are separated by commas, whitespace or line breaks, and a # starts
+// a comment that runs to the end of the line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program(Vec<i64>);
+
+#[derive(Debug)]
+pub enum ProgramError {
+    // The token with the given index (the address it would have been
+    // loaded at) on the given line is not a number. The text is empty if
+    // there is no value before, between or after commas.
+    BadToken {
+        index: usize,
+        line: usize,
+        text: String,
+    },
+    // There are no values at all
+    Empty,
+    Io(io::Error),
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramError::BadToken { index, line, text } => write!(
+                f,
+                "Value {} on line {} is not a number: {:?}",
+                index, line, text
+            ),
+            ProgramError::Empty => write!(f, "The program is empty"),
+            ProgramError::Io(e) => write!(f, "Could not read the program: {}", e),
+        }
+    }
+}
+
+impl Error for ProgramError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProgramError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Program {
+    pub fn parse(text: &str) -> Result<Self, ProgramError> {
+        let mut values = Vec::new();
+        // The line of a comma that has not been followed by a value yet
+        let mut comma = None;
+        let empty = |index, line| ProgramError::BadToken {
+            index,
+            line,
+            text: String::new(),
+        };
+        for (number, line) in text.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or_default();
+            for (idx, part) in code.split(',').enumerate() {
+                // Nothing before the first comma, or between two of them
+                if idx > 0 && (values.is_empty() || comma.is_some()) {
+                    return Err(empty(values.len(), number + 1));
+                }
+                if idx > 0 {
+                    comma = Some(number + 1);
+                }
+                for token in part.split_whitespace() {
+                    let value = token.parse().map_err(|_| ProgramError::BadToken {
+                        index: values.len(),
+                        line: number + 1,
+                        text: token.to_owned(),
+                    })?;
+                    values.push(value);
+                    comma = None;
+                }
+            }
+        }
+        // Nothing after the last comma
+        if let Some(line) = comma {
+            return Err(empty(values.len(), line));
+        }
+        if values.is_empty() {
+            return Err(ProgramError::Empty);
+        }
+        Ok(Program(values))
+    }
+
+    // Read and parse a program file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ProgramError> {
+        Self::parse(&fs::read_to_string(path).map_err(ProgramError::Io)?)
+    }
+}
+
+impl FromStr for Program {
+    type Err = ProgramError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Deref for Program {
+    type Target = [i64];
+
+    fn deref(&self) -> &[i64] {
+        &self.0
+    }
+}
+
+// Lets solvers that take &[i64] use a Program from a generator
+impl Borrow<[i64]> for Program {
+    fn borrow(&self) -> &[i64] {
+        &self.0
+    }
+}
+
+impl From<Vec<i64>> for Program {
+    fn from(values: Vec<i64>) -> Self {
+        Program(values)
+    }
+}
+
+impl From<Program> for Vec<i64> {
+    fn from(program: Program) -> Self {
+        program.0
+    }
+}
+
+impl From<Program> for Computer {
+    fn from(program: Program) -> Self {
+        Computer::from(program.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = "# Outputs its input\n3, 0,\n  4,0 # the output\n\n99\n";
+        assert_eq!(
+            text.parse::<Program>().unwrap(),
+            Program::from(vec![3, 0, 4, 0, 99])
+        );
+        assert_eq!(&*Program::parse("1,2,3").unwrap(), &[1, 2, 3]);
+
+        match Program::parse("1,0,0,0\n99,x7,1") {
+            Err(ProgramError::BadToken { index, line, text }) => {
+                assert_eq!((index, line, text.as_str()), (5, 2, "x7"))
+            }
+            other => panic!("{:?}", other),
+        }
+        for (text, index, line) in &[
+            ("1,,2", 1, 1),
+            (",1", 0, 1),
+            ("1,2,\n,3", 2, 2),
+            ("7,\n", 1, 1),
+        ] {
+            match Program::parse(text) {
+                Err(ProgramError::BadToken {
+                    index: i,
+                    line: l,
+                    text,
+                }) => {
+                    assert_eq!((i, l, text.as_str()), (*index, *line, ""))
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+        assert_eq!(&*Program::parse("1,2 \n\n").unwrap(), &[1, 2]);
+        assert!(matches!(
+            Program::parse(" # nothing\n"),
+            Err(ProgramError::Empty)
+        ));
+        assert!(matches!(
+            Program::load("no/such/program.txt"),
+            Err(ProgramError::Io(_))
+        ));
+        let day9 = Program::load(concat!(env!("CARGO_MANIFEST_DIR"), "/input/2019/day9.txt"));
+        let day9 = day9.unwrap();
+        assert_eq!(day9[..4], [1102, 34463338, 34463338, 63]);
+    }
+}
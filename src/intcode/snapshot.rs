@@ -0,0 +1,286 @@
+use super::{Computer, Memory};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+// The complete state of a Computer at one point in time. It can be
+// restored into any Computer, or written to disk and read back later.
+// Attached input sources and output sinks are not part of it. Pending
+// input is kept in the order Computer::new takes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    // Runs of memory with the address they start at, see Memory::runs
+    memory: Vec<(usize, Vec<i64>)>,
+    procnt: i64,
+    relbse: i64,
+    halted: bool,
+    input: Vec<i64>,
+    output: Vec<i64>,
+}
+
+impl<M: Memory> Computer<M> {
+    // Save the state of the machine.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.runs(),
+            procnt: self.procnt,
+            relbse: self.relbse,
+            halted: self.halted,
+            input: self.input.iter().rev().copied().collect(),
+            output: self.output.clone(),
+        }
+    }
+
+    // Put the machine back in the state it was in when the snapshot was
+    // taken. Settings such as recording and tracing are kept, but the
+    // machine can not step back past this point.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.memory = from_runs(&snapshot.memory);
+        self.procnt = snapshot.procnt;
+        self.relbse = snapshot.relbse;
+        self.halted = snapshot.halted;
+        self.input = snapshot.input.iter().rev().copied().collect();
+        self.output = snapshot.output.clone();
+        self.decoded.clear();
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+}
+
+impl<M: Memory> From<Snapshot> for Computer<M> {
+    // Initialize a computer in the state saved in the snapshot
+    fn from(snapshot: Snapshot) -> Self {
+        Self {
+            memory: from_runs(&snapshot.memory),
+            procnt: snapshot.procnt,
+            relbse: snapshot.relbse,
+            halted: snapshot.halted,
+            input: snapshot.input.into_iter().rev().collect(),
+            output: snapshot.output,
+            ..Default::default()
+        }
+    }
+}
+
+// Memory that holds the runs of a snapshot
+fn from_runs<M: Memory>(runs: &[(usize, Vec<i64>)]) -> M {
+    if let [(0, values)] = runs {
+        return M::from(values.clone());
+    }
+    let mut memory = M::default();
+    for (start, values) in runs {
+        for (offset, &value) in values.iter().enumerate() {
+            memory.poke(start + offset, value);
+        }
+    }
+    memory
+}
+
+// On-disk format. One field per line, in this order, numbers separated by
+// commas. Memory is a list of runs like `0: 1,2,3; 5000: 7`. The header
+// carries a version so the format can evolve; version 1 had all of memory
+// as one list.
+const HEADER: &str = "intcode-snapshot 2";
+const HEADER_V1: &str = "intcode-snapshot 1";
+
+impl Snapshot {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Lists are separated from their key by a space, unless empty
+        let list = |v: &[i64]| {
+            v.iter()
+                .map(|n| format!(",{}", n))
+                .collect::<String>()
+                .replacen(',', " ", 1)
+        };
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "procnt {}", self.procnt)?;
+        writeln!(f, "relbse {}", self.relbse)?;
+        writeln!(f, "halted {}", self.halted as i64)?;
+        writeln!(f, "input{}", list(&self.input))?;
+        writeln!(f, "output{}", list(&self.output))?;
+        let runs: Vec<String> = self
+            .memory
+            .iter()
+            .map(|(start, values)| format!("{}:{}", start, list(values)))
+            .collect();
+        if runs.is_empty() {
+            writeln!(f, "memory")
+        } else {
+            writeln!(f, "memory {}", runs.join("; "))
+        }
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = SnapshotError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate();
+        let version = match lines.next() {
+            Some((_, HEADER)) => 2,
+            Some((_, HEADER_V1)) => 1,
+            _ => return Err(SnapshotError::new(1, "not an intcode snapshot")),
+        };
+        // Read the line with the given key, returning its line number and
+        // the rest of it
+        let mut field = |key: &str| {
+            let (idx, line) = lines
+                .next()
+                .ok_or_else(|| SnapshotError::new(0, &format!("missing {}", key)))?;
+            let rest = line
+                .strip_prefix(key)
+                .ok_or_else(|| SnapshotError::new(idx + 1, &format!("expected {}", key)))?;
+            Ok((idx + 1, rest.trim().to_owned()))
+        };
+        let numbers = |(line, text): (usize, String)| {
+            if text.is_empty() {
+                return Ok(Vec::new());
+            }
+            text.split(',')
+                .map(|n| {
+                    n.trim()
+                        .parse()
+                        .map_err(|_| SnapshotError::new(line, &format!("bad number {:?}", n)))
+                })
+                .collect::<Result<Vec<i64>, _>>()
+        };
+        let single = |v: Vec<i64>, key: &str| match v[..] {
+            [n] => Ok(n),
+            _ => Err(SnapshotError::new(0, &format!("{} needs one value", key))),
+        };
+        let procnt = single(numbers(field("procnt")?)?, "procnt")?;
+        let relbse = single(numbers(field("relbse")?)?, "relbse")?;
+        let halted = single(numbers(field("halted")?)?, "halted")? != 0;
+        let input = numbers(field("input")?)?;
+        let output = numbers(field("output")?)?;
+        let (line, text) = field("memory")?;
+        let memory = if version == 1 {
+            let values = numbers((line, text))?;
+            Some((0, values))
+                .filter(|run| !run.1.is_empty())
+                .into_iter()
+                .collect()
+        } else if text.is_empty() {
+            Vec::new()
+        } else {
+            text.split(';')
+                .map(|run| {
+                    let (start, values) = run
+                        .split_once(':')
+                        .ok_or_else(|| SnapshotError::new(line, &format!("bad run {:?}", run)))?;
+                    let start = start.trim().parse().map_err(|_| {
+                        SnapshotError::new(line, &format!("bad address {:?}", start))
+                    })?;
+                    Ok((start, numbers((line, values.trim().to_owned()))?))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        Ok(Snapshot {
+            memory,
+            procnt,
+            relbse,
+            halted,
+            input,
+            output,
+        })
+    }
+}
+
+// A snapshot file could not be understood. Line 0 means the problem is
+// not tied to a single line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotError {
+    pub line: usize,
+    pub reason: String,
+}
+
+impl SnapshotError {
+    fn new(line: usize, reason: &str) -> Self {
+        Self {
+            line,
+            reason: reason.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid snapshot (line {}): {}", self.line, self.reason)
+    }
+}
+
+impl Error for SnapshotError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{PagedMemory, StopReason};
+
+    #[test]
+    fn test_restore_and_fork() {
+        // Outputs the input plus one, forever
+        let mut computer = Computer::from(vec![3, 9, 101, 1, 9, 9, 4, 9, 1105, 1, 0]);
+        computer.more_input(10);
+        let saved = computer.snapshot();
+        assert_eq!(computer.resume(), StopReason::Output(11));
+
+        let mut fork = computer.clone();
+        fork.more_input(20);
+        assert_eq!(fork.resume(), StopReason::Output(21));
+        assert_eq!(computer.resume(), StopReason::NeedsInput);
+
+        computer.restore(&saved);
+        assert_eq!(computer.resume(), StopReason::Output(11));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut computer = Computer::new(vec![3, 0, 4, 0, 109, -2, 99], vec![5, 6]);
+        computer.resume();
+        computer.resume_for(1);
+        let text = computer.snapshot().to_string();
+        assert_eq!(
+            text,
+            "intcode-snapshot 2\nprocnt 6\nrelbse -2\nhalted 0\ninput 5\noutput\nmemory 0: 6,0,4,0,109,-2,99\n"
+        );
+        let parsed: Snapshot = text.parse().unwrap();
+        assert_eq!(parsed, computer.snapshot());
+        let old = text
+            .replace("snapshot 2", "snapshot 1")
+            .replace("memory 0: ", "memory ");
+        assert_eq!(old.parse::<Snapshot>(), Ok(parsed));
+        assert_eq!(
+            "intcode-snapshot 1\nprocnt x\n".parse::<Snapshot>(),
+            Err(SnapshotError::new(2, "bad number \"x\""))
+        );
+    }
+
+    #[test]
+    fn test_sparse_memory() {
+        // Stores 42 far away and halts
+        let program = vec![1101, 40, 2, 1 << 40, 99];
+        let mut computer = Computer::with_memory(PagedMemory::from(program));
+        computer.run();
+        let text = computer.snapshot().to_string();
+        assert!(text.ends_with("\nmemory 0: 1101,40,2,1099511627776,99; 1099511627776: 42\n"));
+        let restored: Computer<PagedMemory> = Computer::from(text.parse::<Snapshot>().unwrap());
+        assert_eq!(restored.peek(1 << 40), Some(42));
+        assert_eq!(restored.snapshot(), computer.snapshot());
+    }
+}
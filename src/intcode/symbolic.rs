@@ -0,0 +1,531 @@
+// Symbolic execution: some memory cells or inputs are unknowns, and the
+// program builds expressions in them instead of numbers. Control flow has
+// to stay concrete, which is enough for programs like day 2 whose result
+// is a formula in the noun and verb.
+
+use super::{Fault, IntcodeError, Memory};
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::iter;
+use std::ops::{Add, Mul, Range};
+use std::rc::Rc;
+
+// Symbolic runs give up after this many instructions
+const STEP_LIMIT: u64 = 1_000_000;
+
+// A value computed from unknowns. Subexpressions are shared, so moving
+// values around memory does not copy them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Const(i64),
+    // The unknown with this index
+    Var(usize),
+    Add(Rc<Expr>, Rc<Expr>),
+    Mul(Rc<Expr>, Rc<Expr>),
+    LessThan(Rc<Expr>, Rc<Expr>),
+    Equals(Rc<Expr>, Rc<Expr>),
+    // Read from an address that depends on an unknown, so nothing is known
+    // about the value
+    Opaque,
+}
+
+impl Expr {
+    // Comparisons of two constants are folded like sums and products
+    pub fn less_than(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(x), Expr::Const(y)) => Expr::Const((x < y) as i64),
+            (a, b) => Expr::LessThan(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    pub fn equals(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(x), Expr::Const(y)) => Expr::Const((x == y) as i64),
+            (a, b) => Expr::Equals(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    pub fn constant(&self) -> Option<i64> {
+        match self {
+            Expr::Const(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    // The value with the unknowns set to the given values, or None if it
+    // overflows or depends on something else
+    pub fn eval(&self, values: &[i64]) -> Option<i64> {
+        match self {
+            Expr::Const(n) => Some(*n),
+            Expr::Var(v) => values.get(*v).copied(),
+            Expr::Add(a, b) => a.eval(values)?.checked_add(b.eval(values)?),
+            Expr::Mul(a, b) => a.eval(values)?.checked_mul(b.eval(values)?),
+            Expr::LessThan(a, b) => Some((a.eval(values)? < b.eval(values)?) as i64),
+            Expr::Equals(a, b) => Some((a.eval(values)? == b.eval(values)?) as i64),
+            Expr::Opaque => None,
+        }
+    }
+}
+
+// Sums and products fold constants and drop additions of zero and
+// multiplications by one
+impl Add for Expr {
+    type Output = Expr;
+
+    fn add(self, other: Expr) -> Expr {
+        match (self, other) {
+            (Expr::Const(x), Expr::Const(y)) if x.checked_add(y).is_some() => Expr::Const(x + y),
+            (Expr::Const(0), e) | (e, Expr::Const(0)) => e,
+            (a, b) => Expr::Add(Rc::new(a), Rc::new(b)),
+        }
+    }
+}
+
+impl Mul for Expr {
+    type Output = Expr;
+
+    fn mul(self, other: Expr) -> Expr {
+        match (self, other) {
+            (Expr::Const(x), Expr::Const(y)) if x.checked_mul(y).is_some() => Expr::Const(x * y),
+            (Expr::Const(0), _) | (_, Expr::Const(0)) => Expr::Const(0),
+            (Expr::Const(1), e) | (e, Expr::Const(1)) => e,
+            (a, b) => Expr::Mul(Rc::new(a), Rc::new(b)),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    // For example: `((x0 * 3) + x1)`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Const(n) => write!(f, "{}", n),
+            Expr::Var(v) => write!(f, "x{}", v),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b) => write!(f, "({} * {})", a, b),
+            Expr::LessThan(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Equals(a, b) => write!(f, "({} == {})", a, b),
+            Expr::Opaque => write!(f, "?"),
+        }
+    }
+}
+
+// The unknowns in a term of a polynomial and their powers, by index
+type Monomial = Vec<(usize, u32)>;
+
+// A sum of terms with their coefficients, none of them zero
+#[derive(Debug, Clone, Default)]
+struct Polynomial(BTreeMap<Monomial, i64>);
+
+impl Polynomial {
+    // None if the expression has comparisons or opaque parts, or if a
+    // coefficient overflows
+    fn from_expr(expr: &Expr) -> Option<Self> {
+        match expr {
+            Expr::Const(n) => Some(Polynomial::term(Vec::new(), *n)),
+            Expr::Var(v) => Some(Polynomial::term(vec![(*v, 1)], 1)),
+            Expr::Add(a, b) => Self::from_expr(a)?.plus(&Self::from_expr(b)?),
+            Expr::Mul(a, b) => Self::from_expr(a)?.times(&Self::from_expr(b)?),
+            _ => None,
+        }
+    }
+
+    fn term(monomial: Monomial, coefficient: i64) -> Self {
+        let mut terms = BTreeMap::new();
+        if coefficient != 0 {
+            terms.insert(monomial, coefficient);
+        }
+        Polynomial(terms)
+    }
+
+    fn add_term(&mut self, monomial: Monomial, coefficient: i64) -> Option<()> {
+        let sum = self
+            .0
+            .get(&monomial)
+            .unwrap_or(&0)
+            .checked_add(coefficient)?;
+        if sum == 0 {
+            self.0.remove(&monomial);
+        } else {
+            self.0.insert(monomial, sum);
+        }
+        Some(())
+    }
+
+    fn plus(mut self, other: &Polynomial) -> Option<Self> {
+        for (monomial, &coefficient) in &other.0 {
+            self.add_term(monomial.clone(), coefficient)?;
+        }
+        Some(self)
+    }
+
+    fn times(&self, other: &Polynomial) -> Option<Self> {
+        let mut product = Polynomial::default();
+        for (left, &a) in &self.0 {
+            for (right, &b) in &other.0 {
+                let mut powers: BTreeMap<usize, u32> = left.iter().copied().collect();
+                for &(var, power) in right {
+                    *powers.entry(var).or_default() += power;
+                }
+                product.add_term(powers.into_iter().collect(), a.checked_mul(b)?)?;
+            }
+        }
+        Some(product)
+    }
+
+    // The highest power of the unknown in any term
+    fn degree(&self, var: usize) -> u32 {
+        self.0
+            .keys()
+            .flat_map(|monomial| monomial.iter().filter(|&&(v, _)| v == var))
+            .map(|&(_, power)| power)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // For a polynomial of degree 1 in var, the a and b in `a * var + b`
+    fn split(&self, var: usize) -> (Polynomial, Polynomial) {
+        let (mut slope, mut rest) = (Polynomial::default(), Polynomial::default());
+        for (monomial, &coefficient) in &self.0 {
+            if monomial.iter().any(|&(v, _)| v == var) {
+                let others = monomial.iter().filter(|&&(v, _)| v != var).copied();
+                slope.0.insert(others.collect(), coefficient);
+            } else {
+                rest.0.insert(monomial.clone(), coefficient);
+            }
+        }
+        (slope, rest)
+    }
+
+    fn eval(&self, values: &[i64]) -> Option<i64> {
+        self.0
+            .iter()
+            .try_fold(0_i64, |sum, (monomial, &coefficient)| {
+                let term = monomial
+                    .iter()
+                    .try_fold(coefficient, |term, &(var, power)| {
+                        term.checked_mul(values.get(var)?.checked_pow(power)?)
+                    })?;
+                sum.checked_add(term)
+            })
+    }
+}
+
+// Every combination of values from the domains, in lexicographic order
+fn combinations(domains: &[Range<i64>]) -> impl Iterator<Item = Vec<i64>> + '_ {
+    let first = if domains.iter().any(|domain| domain.start >= domain.end) {
+        None
+    } else {
+        Some(domains.iter().map(|domain| domain.start).collect())
+    };
+    iter::successors(first, move |values: &Vec<i64>| {
+        let mut values = values.clone();
+        for idx in (0..domains.len()).rev() {
+            values[idx] += 1;
+            if values[idx] < domains[idx].end {
+                return Some(values);
+            }
+            values[idx] = domains[idx].start;
+        }
+        None
+    })
+}
+
+// Find values for the unknowns, each from its domain, that make the
+// expression equal to the target. A polynomial of degree 1 in some unknown
+// is solved for it directly, trying only the values of the others;
+// anything else is evaluated for every combination.
+pub fn solve(expr: &Expr, target: i64, domains: &[Range<i64>]) -> Option<Vec<i64>> {
+    if let Some(poly) = Polynomial::from_expr(expr) {
+        if let Some(var) = (0..domains.len()).rev().find(|&v| poly.degree(v) == 1) {
+            let (slope, rest) = poly.split(var);
+            let mut others = domains.to_vec();
+            others[var] = 0..1;
+            return combinations(&others).find_map(|mut values| {
+                let (a, b) = (slope.eval(&values)?, rest.eval(&values)?);
+                let difference = target.checked_sub(b)?;
+                values[var] = match a {
+                    0 if difference == 0 => domains[var].start,
+                    0 => return None,
+                    a if difference % a == 0 => difference / a,
+                    _ => return None,
+                };
+                Some(values).filter(|values| domains[var].contains(&values[var]))
+            });
+        }
+    }
+    combinations(domains).find(|values| expr.eval(values) == Some(target))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolicError {
+    // The instruction at procnt needed a number for the given purpose, such
+    // as a jump target, but got an expression
+    NotConcrete { procnt: i64, what: &'static str },
+    Fault(Fault),
+    // Still running after STEP_LIMIT instructions
+    StepLimit,
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolicError::NotConcrete { procnt, what } => write!(
+                f,
+                "The {} of the instruction at {} depends on an unknown",
+                what, procnt
+            ),
+            SymbolicError::Fault(fault) => write!(f, "{}", fault),
+            SymbolicError::StepLimit => write!(f, "Still running after {} steps", STEP_LIMIT),
+        }
+    }
+}
+
+impl Error for SymbolicError {}
+
+// Runs a program on memory and input that may contain expressions
+#[derive(Debug, Clone)]
+pub struct Symbolic {
+    memory: Vec<Expr>,
+    procnt: i64,
+    relbase: i64,
+    input: VecDeque<Expr>,
+    output: Vec<Expr>,
+}
+
+impl Symbolic {
+    pub fn new(program: &[i64]) -> Self {
+        Symbolic {
+            memory: program.iter().map(|&n| Expr::Const(n)).collect(),
+            procnt: 0,
+            relbase: 0,
+            input: VecDeque::new(),
+            output: Vec::new(),
+        }
+    }
+
+    // Put a value, usually Expr::Var, in memory before running
+    pub fn set(&mut self, address: usize, value: Expr) {
+        if self.memory.len() <= address {
+            self.memory.resize(address + 1, Expr::Const(0));
+        }
+        self.memory[address] = value;
+    }
+
+    pub fn more_input(&mut self, value: Expr) {
+        self.input.push_back(value);
+    }
+
+    pub fn memory(&self, address: usize) -> Expr {
+        self.memory.get(address).cloned().unwrap_or(Expr::Const(0))
+    }
+
+    pub fn output(&self) -> &[Expr] {
+        &self.output
+    }
+
+    // Run until the program halts
+    pub fn run(&mut self) -> Result<(), SymbolicError> {
+        for _ in 0..STEP_LIMIT {
+            if self.step()? {
+                return Ok(());
+            }
+        }
+        Err(SymbolicError::StepLimit)
+    }
+
+    fn fault(&self, error: IntcodeError) -> SymbolicError {
+        SymbolicError::Fault(Fault {
+            procnt: self.procnt,
+            error,
+        })
+    }
+
+    fn concrete(&self, value: Expr, what: &'static str) -> Result<i64, SymbolicError> {
+        value.constant().ok_or(SymbolicError::NotConcrete {
+            procnt: self.procnt,
+            what,
+        })
+    }
+
+    // The address parameter idx refers to, or None in immediate mode
+    fn address(&self, word: i64, idx: u32) -> Result<Option<Expr>, SymbolicError> {
+        let operand = self.memory(self.procnt as usize + idx as usize);
+        match word / 10_i64.pow(idx + 1) % 10 {
+            0 => Ok(Some(operand)),
+            1 => Ok(None),
+            2 => Ok(Some(Expr::Const(self.relbase) + operand)),
+            _ => Err(self.fault(IntcodeError::UnknownOpcode(word))),
+        }
+    }
+
+    // Fail for addresses that a Computer with the default memory does not
+    // have
+    fn check_address(&self, address: i64) -> Result<usize, SymbolicError> {
+        let limit = <Vec<i64> as Memory>::ADDRESS_LIMIT.map_or(i64::MAX, |limit| limit as i64);
+        if address < 0 {
+            return Err(self.fault(IntcodeError::NegativeAddress(address)));
+        }
+        if address >= limit {
+            return Err(self.fault(IntcodeError::AddressTooHigh(address)));
+        }
+        Ok(address as usize)
+    }
+
+    fn read(&self, word: i64, idx: u32) -> Result<Expr, SymbolicError> {
+        match self.address(word, idx)? {
+            None => Ok(self.memory(self.procnt as usize + idx as usize)),
+            Some(Expr::Const(a)) => Ok(self.memory(self.check_address(a)?)),
+            Some(_) => Ok(Expr::Opaque),
+        }
+    }
+
+    fn write(&mut self, word: i64, idx: u32, value: Expr) -> Result<(), SymbolicError> {
+        match self.address(word, idx)? {
+            None => Err(self.fault(IntcodeError::ImmediateWrite)),
+            Some(Expr::Const(a)) => {
+                let address = self.check_address(a)?;
+                self.set(address, value);
+                Ok(())
+            }
+            Some(_) => Err(SymbolicError::NotConcrete {
+                procnt: self.procnt,
+                what: "write address",
+            }),
+        }
+    }
+
+    // Execute one instruction. Returns true on halt.
+    fn step(&mut self) -> Result<bool, SymbolicError> {
+        if self.procnt < 0 || self.procnt as usize >= self.memory.len() {
+            return Err(self.fault(IntcodeError::PcOutOfBounds));
+        }
+        let word = self.concrete(self.memory(self.procnt as usize), "opcode")?;
+        match word % 100 {
+            opcode @ 1 | opcode @ 2 | opcode @ 7 | opcode @ 8 => {
+                let (a, b) = (self.read(word, 1)?, self.read(word, 2)?);
+                // Constants that overflow fault, as they do in a Computer
+                let overflow = match (opcode, a.constant(), b.constant()) {
+                    (1, Some(x), Some(y)) => x.checked_add(y).is_none(),
+                    (2, Some(x), Some(y)) => x.checked_mul(y).is_none(),
+                    _ => false,
+                };
+                if overflow {
+                    return Err(self.fault(IntcodeError::Overflow));
+                }
+                let value = match opcode {
+                    1 => a + b,
+                    2 => a * b,
+                    7 => Expr::less_than(a, b),
+                    _ => Expr::equals(a, b),
+                };
+                self.write(word, 3, value)?;
+                self.procnt += 4;
+            }
+            3 => {
+                let value = self
+                    .input
+                    .pop_front()
+                    .ok_or_else(|| self.fault(IntcodeError::InputExhausted))?;
+                self.write(word, 1, value)?;
+                self.procnt += 2;
+            }
+            4 => {
+                let value = self.read(word, 1)?;
+                self.output.push(value);
+                self.procnt += 2;
+            }
+            opcode @ 5 | opcode @ 6 => {
+                let test = self.concrete(self.read(word, 1)?, "jump condition")?;
+                if (test != 0) == (opcode == 5) {
+                    self.procnt = self.concrete(self.read(word, 2)?, "jump target")?;
+                } else {
+                    self.procnt += 3;
+                }
+            }
+            9 => {
+                let offset = self.concrete(self.read(word, 1)?, "relative base")?;
+                self.relbase = self
+                    .relbase
+                    .checked_add(offset)
+                    .ok_or_else(|| self.fault(IntcodeError::Overflow))?;
+                self.procnt += 2;
+            }
+            99 => return Ok(true),
+            _ => return Err(self.fault(IntcodeError::UnknownOpcode(word))),
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve() {
+        // 3 * x0 + x1 + 4
+        let linear = Expr::Const(3) * Expr::Var(0) + Expr::Var(1) + Expr::Const(4);
+        assert_eq!(solve(&linear, 20, &[0..10, 0..10]), Some(vec![3, 7]));
+        assert_eq!(solve(&linear, 20, &[0..10, 0..3]), Some(vec![5, 1]));
+        assert_eq!(solve(&linear, 1000, &[0..10, 0..10]), None);
+
+        // x0 * x0 + x1 is linear in x1; x0 * x0 + x1 * x1 is not linear
+        let square = |v| Expr::Var(v) * Expr::Var(v);
+        let parabola = square(0) + Expr::Var(1);
+        assert_eq!(solve(&parabola, 30, &[0..10, 0..10]), Some(vec![5, 5]));
+        let circle = square(0) + square(1);
+        assert_eq!(solve(&circle, 25, &[-10..10, 1..10]), Some(vec![-4, 3]));
+
+        // Comparisons are not polynomials, so every value is tried
+        let compare = Expr::less_than(Expr::Var(0), Expr::Var(1) + Expr::Const(5));
+        assert_eq!(solve(&compare, 0, &[0..10, 0..3]), Some(vec![5, 0]));
+        assert_eq!(compare.to_string(), "(x0 < (x1 + 5))");
+    }
+
+    #[test]
+    fn test_run() {
+        // mem[20] := input, then add mem[21] to it twice in a concrete loop
+        // and output double the sum
+        let program = [
+            3, 20, 1, 20, 21, 20, 1001, 22, -1, 22, 1005, 22, 2, 1002, 20, 2, 23, 4, 23, 99, 0, 0,
+            2,
+        ];
+        let mut machine = Symbolic::new(&program);
+        machine.set(21, Expr::Var(1));
+        machine.more_input(Expr::Var(0));
+        machine.run().unwrap();
+        let output = machine.output()[0].clone();
+        assert_eq!(output.to_string(), "(((x0 + x1) + x1) * 2)");
+        assert_eq!(solve(&output, 14, &[0..10, 0..10]), Some(vec![1, 3]));
+
+        // Jumping on an unknown
+        let mut machine = Symbolic::new(&[3, 7, 1005, 7, 6, 99, 99]);
+        machine.more_input(Expr::Var(0));
+        assert_eq!(
+            machine.run(),
+            Err(SymbolicError::NotConcrete {
+                procnt: 2,
+                what: "jump condition"
+            })
+        );
+
+        // Faults that a Computer would also report
+        let fault = |program: &[i64]| match Symbolic::new(program).run() {
+            Err(SymbolicError::Fault(fault)) => fault.to_string(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(
+            fault(&[1101, 1, 1, 1 << 30, 99]),
+            "Access to address 1073741824 above the limit at PC = 0"
+        );
+        assert_eq!(
+            fault(&[109, i64::MAX, 109, 1, 99]),
+            "Arithmetic overflow at PC = 2"
+        );
+        assert_eq!(
+            fault(&[1102, 1 << 40, 1 << 40, 0, 99]),
+            "Arithmetic overflow at PC = 0"
+        );
+    }
+}
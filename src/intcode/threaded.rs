@@ -0,0 +1,216 @@
+use super::{Computer, Fault, Limit, StopReason};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Instructions to run between checks for a stop request
+const SLICE: usize = 10_000;
+// How long to block on input before checking for a stop request
+const POLL: Duration = Duration::from_millis(10);
+
+// What a Computer running on its own thread is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Running,
+    // Blocked until a value arrives on the input channel
+    WaitingForInput,
+    Halted,
+    // Stopped on request, or because the input channel was closed while
+    // the program wanted input
+    Stopped,
+    Faulted,
+    // Ended because the computer reached a limit set with set_limits
+    LimitReached(Limit),
+    // Ended at a breakpoint or watchpoint of the computer
+    AtBreak,
+}
+
+// Handle to a Computer running on its own thread. Input arrives over a
+// channel and output leaves over another; the output channel closes when
+// the thread ends, so a reader can tell halting from waiting.
+#[derive(Debug)]
+pub struct ComputerThread {
+    state: Arc<Mutex<ThreadState>>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Result<Computer, Fault>>,
+}
+
+impl ComputerThread {
+    // Start the computer on a new thread, with new channels. Returns the
+    // handle, the sending end of the input and the receiving end of the
+    // output.
+    pub fn spawn(computer: Computer) -> (Self, Sender<i64>, Receiver<i64>) {
+        let (input_tx, input_rx) = channel();
+        let (output_tx, output_rx) = channel();
+        (
+            Self::spawn_with(computer, input_rx, output_tx),
+            input_tx,
+            output_rx,
+        )
+    }
+
+    // Start the computer on a new thread, reading from and writing to the
+    // given channels. Use this to connect several threads to each other.
+    // Values already in the computer's input buffer are read first.
+    pub fn spawn_with(mut computer: Computer, input: Receiver<i64>, output: Sender<i64>) -> Self {
+        let state = Arc::new(Mutex::new(ThreadState::Running));
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let set = |s| *state.lock().unwrap() = s;
+                let end = loop {
+                    if stop.load(Ordering::Relaxed) {
+                        break ThreadState::Stopped;
+                    }
+                    match computer.try_resume_for(SLICE) {
+                        Ok(StopReason::Output(value)) => {
+                            // Nobody listening is not our problem
+                            let _ = output.send(value);
+                        }
+                        Ok(StopReason::NeedsInput) => {
+                            set(ThreadState::WaitingForInput);
+                            match input.recv_timeout(POLL) {
+                                Ok(value) => {
+                                    computer.more_input(value);
+                                    set(ThreadState::Running);
+                                }
+                                // Still waiting, after checking for a stop
+                                Err(RecvTimeoutError::Timeout) => {}
+                                Err(RecvTimeoutError::Disconnected) => {
+                                    break ThreadState::Stopped;
+                                }
+                            }
+                        }
+                        Ok(StopReason::Halted) => break ThreadState::Halted,
+                        Ok(StopReason::Limit(limit)) => break ThreadState::LimitReached(limit),
+                        Ok(StopReason::Break(_)) => break ThreadState::AtBreak,
+                        // The end of a slice, time to check for a stop
+                        Ok(StopReason::StepLimit) => {}
+                        Err(fault) => {
+                            set(ThreadState::Faulted);
+                            return Err(fault);
+                        }
+                    }
+                };
+                set(end);
+                Ok(computer)
+            })
+        };
+        Self {
+            state,
+            stop,
+            handle,
+        }
+    }
+
+    // What the thread is doing right now. This can change as soon as it
+    // has been read.
+    pub fn state(&self) -> ThreadState {
+        *self.state.lock().unwrap()
+    }
+
+    // Ask the thread to stop and wait for it. Returns the computer as it
+    // was when it stopped, or the fault that ended it earlier.
+    pub fn stop(self) -> Result<Computer, Fault> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.join()
+    }
+
+    // Wait for the thread to end by itself: the program halts, faults,
+    // reaches a limit or breakpoint, or wants input after the input
+    // channel has been closed.
+    pub fn join(self) -> Result<Computer, Fault> {
+        self.handle.join().expect("Computer thread panicked")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{IntcodeError, Limits};
+
+    // Adds up its inputs until it reads a zero, then outputs the sum
+    fn adder() -> Computer {
+        Computer::from(vec![
+            3, 100, 1006, 100, 12, 1, 100, 101, 101, 1105, 1, 0, 4, 101, 99,
+        ])
+    }
+
+    #[test]
+    fn test_io_and_halt() {
+        let (thread, input, output) = ComputerThread::spawn(adder());
+        input.send(5).unwrap();
+        input.send(7).unwrap();
+        while thread.state() != ThreadState::WaitingForInput {
+            thread::yield_now();
+        }
+        // It keeps waiting, across several polls
+        for _ in 0..5 {
+            thread::sleep(POLL);
+            assert_eq!(thread.state(), ThreadState::WaitingForInput);
+        }
+        input.send(0).unwrap();
+        assert_eq!(output.recv(), Ok(12));
+        assert!(output.recv().is_err());
+        assert_eq!(thread.state(), ThreadState::Halted);
+        assert!(thread.join().unwrap().is_halted());
+    }
+
+    #[test]
+    fn test_stop() {
+        let (thread, input, output) = ComputerThread::spawn(adder());
+        input.send(1).unwrap();
+        let computer = thread.stop().unwrap();
+        assert!(!computer.is_halted());
+        assert!(output.try_recv().is_err());
+
+        // Closing the input ends a waiting thread too
+        let (thread, input, _output) = ComputerThread::spawn(adder());
+        drop(input);
+        assert!(thread.join().is_ok());
+    }
+
+    #[test]
+    fn test_chain_and_fault() {
+        let (first_tx, first_rx) = channel();
+        let (link_tx, link_rx) = channel();
+        let (last_tx, last_rx) = channel();
+        let first = ComputerThread::spawn_with(adder(), first_rx, link_tx);
+        let second = ComputerThread::spawn_with(adder(), link_rx, last_tx);
+        for value in &[1, 2, 3, 0] {
+            first_tx.send(*value).unwrap();
+        }
+        // The second adder needs a zero after the sum of the first
+        drop(first_tx);
+        assert!(first.join().is_ok());
+        let computer = second.stop().unwrap();
+        assert!(!computer.is_halted());
+        assert!(last_rx.try_recv().is_err());
+
+        let (thread, _, _) = ComputerThread::spawn(Computer::from(vec![42]));
+        assert_eq!(
+            thread.join().unwrap_err().error,
+            IntcodeError::UnknownOpcode(42)
+        );
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut spinner = Computer::from(vec![1105, 1, 0]);
+        spinner.set_limits(Limits {
+            instructions: Some(100),
+            ..Default::default()
+        });
+        let (thread, _input, output) = ComputerThread::spawn(spinner);
+        assert!(output.recv().is_err());
+        assert_eq!(
+            thread.state(),
+            ThreadState::LimitReached(Limit::Instructions)
+        );
+        assert_eq!(thread.join().unwrap().usage(), (100, 0));
+    }
+}
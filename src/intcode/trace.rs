@@ -0,0 +1,327 @@
+use super::{builtin, Computer, Memory, Mode};
+use crate::intcode_asm::operand_to_string;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+// What happened during one instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub procnt: i64,
+    pub opcode: i64,
+    // Mnemonic of the opcode in the Computer's instruction set
+    pub name: String,
+    pub params: Vec<Param>,
+    // Address and value of the memory cell that was written
+    pub write: Option<(i64, i64)>,
+    // New relative base, if it was adjusted
+    pub relbase: Option<i64>,
+    pub input: Option<i64>,
+    pub output: Option<i64>,
+}
+
+// One parameter of a traced instruction. The value is what was read; it
+// is None for the parameter that names the destination of a write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Param {
+    pub mode: Mode,
+    pub operand: i64,
+    pub value: Option<i64>,
+}
+
+// Receives one event for every instruction a traced Computer executes.
+pub trait TraceSink {
+    fn event(&mut self, event: &TraceEvent);
+}
+
+// Shared handle to a sink, so the caller can still look at it while the
+// Computer (or several clones of it) write to it.
+pub type SharedSink = Arc<Mutex<dyn TraceSink + Send>>;
+
+#[derive(Clone)]
+pub(super) struct Tracer(SharedSink);
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tracer")
+    }
+}
+
+impl<M: Memory> Computer<M> {
+    // Send an event for every instruction executed from now on to the
+    // sink. Replaces any sink that was set before.
+    pub fn trace_to(&mut self, sink: SharedSink) {
+        self.tracer = Some(Tracer(sink));
+    }
+
+    pub fn stop_tracing(&mut self) {
+        self.tracer = None;
+    }
+
+    // Describe the instruction at the program counter before it runs. The
+    // effects are filled in by finish_event afterwards. Returns None if
+    // the instruction can not be decoded; it will fault instead.
+    pub(super) fn start_event(&mut self) -> Option<TraceEvent> {
+        let ins = self.instruction().ok()?;
+        let write_param = ins.write_param();
+        let params = (0..ins.length() - 1)
+            .map(|idx| Param {
+                mode: ins.modes[idx],
+                operand: ins.operands[idx],
+                value: match write_param {
+                    Some(w) if w == idx => None,
+                    _ => self.read(&ins, idx).ok(),
+                },
+            })
+            .collect();
+        let write = write_param
+            .and_then(|idx| self.destination(&ins, idx).ok())
+            .map(|address| (address, 0));
+        Some(TraceEvent {
+            procnt: self.procnt,
+            opcode: ins.opcode,
+            name: self
+                .instruction_set()
+                .name(ins.opcode)
+                .unwrap_or_else(|| opcode_name(ins.opcode))
+                .to_owned(),
+            params,
+            write,
+            relbase: None,
+            input: None,
+            output: None,
+        })
+    }
+
+    pub(super) fn finish_event(&self, mut event: TraceEvent) {
+        if let Some((address, value)) = &mut event.write {
+            *value = self.memory.peek(*address as usize);
+        }
+        match event.opcode {
+            3 => event.input = event.write.map(|(_, value)| value),
+            4 => event.output = event.params[0].value,
+            9 => event.relbase = Some(self.relbse),
+            _ => {}
+        }
+        if let Some(Tracer(sink)) = &self.tracer {
+            sink.lock().unwrap().event(&event);
+        }
+    }
+}
+
+// Mnemonic for an opcode, as used in the text log
+pub fn opcode_name(opcode: i64) -> &'static str {
+    builtin(opcode).map_or("???", |(name, _, _)| name)
+}
+
+impl fmt::Display for TraceEvent {
+    // One line: the instruction with each operand rendered like in the
+    // disassembly (and the value it read), followed by its effects.
+    // For example: `  12: add *9=3 5 rb[2] ; *40 := 8`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:4}: {}", self.procnt, self.name)?;
+        for p in &self.params {
+            write!(f, " {}", operand_to_string(p.operand, p.mode))?;
+            if let (Some(value), false) = (p.value, p.mode == Mode::Immediate) {
+                write!(f, "={}", value)?;
+            }
+        }
+        let mut effects = Vec::new();
+        if let Some(value) = self.input {
+            effects.push(format!("input {}", value));
+        }
+        if let Some((address, value)) = self.write {
+            effects.push(format!("*{} := {}", address, value));
+        }
+        if let Some(value) = self.output {
+            effects.push(format!("output {}", value));
+        }
+        if let Some(value) = self.relbase {
+            effects.push(format!("rb := {}", value));
+        }
+        if !effects.is_empty() {
+            write!(f, " ; {}", effects.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// Keeps the last <capacity> events in memory
+#[derive(Debug, Clone)]
+pub struct RingBuffer {
+    capacity: usize,
+    events: VecDeque<TraceEvent>,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    // The retained events, oldest first
+    pub fn events(&self) -> impl Iterator<Item = &TraceEvent> {
+        self.events.iter()
+    }
+}
+
+impl TraceSink for RingBuffer {
+    fn event(&mut self, event: &TraceEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event.clone());
+    }
+}
+
+// Writes every event as one line of text. Write errors end the log; the
+// first one is reported by into_inner.
+#[derive(Debug)]
+pub struct TextLog<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TextLog<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn into_inner(self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.writer),
+        }
+    }
+}
+
+impl<W: Write> TraceSink for TextLog<W> {
+    fn event(&mut self, event: &TraceEvent) {
+        if self.error.is_none() {
+            self.error = writeln!(self.writer, "{}", event).err();
+        }
+    }
+}
+
+// Writes every event as a JSON object on its own line. Write errors are
+// handled like in TextLog.
+#[derive(Debug)]
+pub struct JsonLines<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonLines<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn into_inner(self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.writer),
+        }
+    }
+}
+
+impl<W: Write> TraceSink for JsonLines<W> {
+    fn event(&mut self, event: &TraceEvent) {
+        if self.error.is_none() {
+            self.error = writeln!(self.writer, "{}", json(event)).err();
+        }
+    }
+}
+
+fn json(event: &TraceEvent) -> String {
+    let number = |n: Option<i64>| n.map_or("null".to_owned(), |n| n.to_string());
+    let params = event
+        .params
+        .iter()
+        .map(|p| {
+            format!(
+                r#"{{"mode":"{}","operand":{},"value":{}}}"#,
+                match p.mode {
+                    Mode::Immediate => "immediate",
+                    Mode::Position => "position",
+                    Mode::Relative => "relative",
+                },
+                p.operand,
+                number(p.value)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    let write = event.write.map_or("null".to_owned(), |(address, value)| {
+        format!(r#"{{"address":{},"value":{}}}"#, address, value)
+    });
+    format!(
+        r#"{{"pc":{},"opcode":{},"params":[{}],"write":{},"relbase":{},"input":{},"output":{}}}"#,
+        event.procnt,
+        event.opcode,
+        params,
+        write,
+        number(event.relbase),
+        number(event.input),
+        number(event.output)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(program: Vec<i64>, input: Vec<i64>, sink: SharedSink) {
+        let mut computer = Computer::new(program, input);
+        computer.trace_to(sink);
+        computer.run();
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let ring = Arc::new(Mutex::new(RingBuffer::new(2)));
+        trace(vec![3, 7, 109, 3, 204, 4, 99, 0], vec![5], ring.clone());
+        let ring = ring.lock().unwrap();
+        let events: Vec<_> = ring.events().collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].output, Some(5));
+        assert_eq!(events[1].opcode, 99);
+    }
+
+    #[test]
+    fn test_text_log() {
+        let log = Arc::new(Mutex::new(TextLog::new(Vec::new())));
+        trace(vec![3, 7, 109, 3, 204, 4, 99, 0], vec![5], log.clone());
+        let log = Arc::try_unwrap(log).ok().unwrap().into_inner().unwrap();
+        assert_eq!(
+            String::from_utf8(log.into_inner().unwrap()).unwrap(),
+            "   0: in *7 ; input 5, *7 := 5\n\
+             \x20  2: arb 3 ; rb := 3\n\
+             \x20  4: out rb[4]=5 ; output 5\n\
+             \x20  6: halt\n"
+        );
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let log = Arc::new(Mutex::new(JsonLines::new(Vec::new())));
+        trace(vec![1001, 5, 2, 5, 99, 40], vec![], log.clone());
+        let log = Arc::try_unwrap(log).ok().unwrap().into_inner().unwrap();
+        let text = String::from_utf8(log.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            text.lines().next().unwrap(),
+            r#"{"pc":0,"opcode":1,"params":[{"mode":"position","operand":5,"value":40},{"mode":"immediate","operand":2,"value":2},{"mode":"position","operand":5,"value":null}],"write":{"address":5,"value":42},"relbase":null,"input":null,"output":null}"#
+        );
+    }
+}
@@ -0,0 +1,465 @@
+use crate::intcode::Mode;
+use crate::intcode_asm::Op;
+use std::collections::HashSet;
+
+// Translate an Intcode program into the source of a Rust module called
+// <name>, with one function:
+//
+//     pub fn run(
+//         input: &mut dyn FnMut() -> Option<i64>,
+//         output: &mut dyn FnMut(i64),
+//     ) -> Result<Vec<i64>, String>
+//
+// It runs the program to the end and returns its memory, or the same
+// message a Computer would give for a fault. Every instruction found in
+// the program becomes an arm of a `match pc`. Jumps to anywhere else are
+// interpreted, and once the program writes to its own code the rest of
+// the run is interpreted too. The module needs nothing outside std.
+pub fn transpile(program: &[i64], name: &str) -> String {
+    let code = find_code(program);
+    let mut is_code = vec![false; program.len()];
+    for (address, op) in code.iter().enumerate() {
+        if let Some(op) = op {
+            for flag in is_code.iter_mut().skip(address).take(op.length()) {
+                *flag = true;
+            }
+        }
+    }
+    let arms: String = code
+        .iter()
+        .enumerate()
+        .filter_map(|(address, op)| op.as_ref().map(|op| arm(address as i64, op)))
+        .collect();
+    let list = |values: Vec<String>| values.join(", ");
+    let mut source = format!(
+        "// Transpiled from an Intcode program of {len} values by intcode-transpile\n\
+         #[allow(dead_code, unused_parens, clippy::all)]\n\
+         pub mod {name} {{\n    \
+         const PROGRAM: [i64; {len}] = [{program}];\n    \
+         // Addresses that hold transpiled instructions\n    \
+         const CODE: [bool; {len}] = [{code}];\n\n",
+        name = name,
+        len = program.len(),
+        program = list(program.iter().map(|n| n.to_string()).collect()),
+        code = list(is_code.iter().map(|b| b.to_string()).collect()),
+    );
+    source += RUN_START;
+    source += &arms;
+    source += RUN_END;
+    source += MACHINE;
+    source += "}\n";
+    source
+}
+
+// Find the instructions that can be reached from address 0, following
+// jumps to fixed addresses. The code after an unconditional jump or a
+// halt is only taken if its address appears as an immediate value
+// somewhere, as the return address of a call would.
+pub(crate) fn find_code(program: &[i64]) -> Vec<Option<Op>> {
+    let mut code = vec![None; program.len()];
+    let mut immediates = HashSet::new();
+    let mut todo = vec![0];
+    let mut maybe = Vec::new();
+    loop {
+        while let Some(address) = todo.pop() {
+            if address >= program.len() || code[address].is_some() {
+                continue;
+            }
+            let op = match Op::decode(program, address) {
+                Some(op) if !writes_immediate(&op) => op,
+                _ => continue,
+            };
+            for &(value, mode) in &op.params {
+                if mode == Mode::Immediate {
+                    immediates.insert(value);
+                }
+            }
+            let next = address + op.length();
+            match op.opcode {
+                5 | 6 => {
+                    let (test, test_mode) = op.params[0];
+                    let (target, target_mode) = op.params[1];
+                    if target_mode == Mode::Immediate && target >= 0 {
+                        todo.push(target as usize);
+                    }
+                    if test_mode == Mode::Immediate && (test != 0) == (op.opcode == 5) {
+                        maybe.push(next);
+                    } else {
+                        todo.push(next);
+                    }
+                }
+                99 => maybe.push(next),
+                _ => todo.push(next),
+            }
+            code[address] = Some(op);
+        }
+        let (found, rest): (Vec<usize>, _) = maybe
+            .into_iter()
+            .partition(|&address| immediates.contains(&(address as i64)));
+        if found.is_empty() {
+            return code;
+        }
+        todo = found;
+        maybe = rest;
+    }
+}
+
+fn writes_immediate(op: &Op) -> bool {
+    op.write_param()
+        .is_some_and(|idx| op.params[idx].1 == Mode::Immediate)
+}
+
+// The match arm that executes the instruction at <address>
+fn arm(address: i64, op: &Op) -> String {
+    let load = |idx: usize| {
+        let (operand, mode) = op.params[idx];
+        match mode {
+            Mode::Immediate => format!("({})", operand),
+            Mode::Position => format!("m.rd({}, {})?", address, operand),
+            Mode::Relative => format!("m.rd({0}, m.rel({0}, {1})?)?", address, operand),
+        }
+    };
+    let store = |idx: usize| {
+        let (operand, mode) = op.params[idx];
+        match mode {
+            Mode::Relative => format!("m.dst({0}, m.rel({0}, {1})?)?", address, operand),
+            _ => format!("m.dst({}, {})?", address, operand),
+        }
+    };
+    let next = address + op.length() as i64;
+    let body = match op.opcode {
+        1 | 2 | 7 | 8 => format!(
+            "let a: i64 = {}; let b: i64 = {}; let v = {}; let d = {}; m.wr(d, v); m.pc = {};",
+            load(0),
+            load(1),
+            match op.opcode {
+                1 => format!("a.checked_add(b).ok_or_else(|| overflow({}))?", address),
+                2 => format!("a.checked_mul(b).ok_or_else(|| overflow({}))?", address),
+                7 => "(a < b) as i64".to_owned(),
+                _ => "(a == b) as i64".to_owned(),
+            },
+            store(2),
+            next
+        ),
+        3 => format!(
+            "let d = {}; let v = input().ok_or_else(|| \
+             \"Input was taken but none is left at PC = {}\".to_owned())?; \
+             m.wr(d, v); m.pc = {};",
+            store(0),
+            address,
+            next
+        ),
+        4 => format!("output({}); m.pc = {};", load(0), next),
+        5 | 6 => format!(
+            "let a = {}; let b = {}; m.pc = if a {} 0 {{ b }} else {{ {} }};",
+            load(0),
+            load(1),
+            if op.opcode == 5 { "!=" } else { "==" },
+            next
+        ),
+        9 => format!(
+            "let a = {}; m.rb = m.rel({}, a)?; m.pc = {};",
+            load(0),
+            address,
+            next
+        ),
+        _ => "return Ok(m.mem);".to_owned(),
+    };
+    format!("                {} => {{ {} }}\n", address, body)
+}
+
+// The parts of the generated module that are the same for every program
+
+const RUN_START: &str = "    // Run the program to the end. Returns its memory when it halts.
+    pub fn run(
+        input: &mut dyn FnMut() -> Option<i64>,
+        output: &mut dyn FnMut(i64),
+    ) -> Result<Vec<i64>, String> {
+        let mut m = Machine {
+            mem: PROGRAM.to_vec(),
+            pc: 0,
+            rb: 0,
+            dirty: false,
+        };
+        while !m.dirty {
+            match m.pc {
+";
+
+const RUN_END: &str = "                _ => {
+                    if m.step(input, output)? {
+                        return Ok(m.mem);
+                    }
+                }
+            }
+        }
+        // The program changed its own code: interpret the rest
+        while !m.step(input, output)? {}
+        Ok(m.mem)
+    }
+";
+
+const MACHINE: &str = r#"
+    struct Machine {
+        mem: Vec<i64>,
+        pc: i64,
+        rb: i64,
+        // Set once the program writes to its own code
+        dirty: bool,
+    }
+
+    // Addresses from here on do not exist, as for a Computer
+    const LIMIT: i64 = 1 << 24;
+
+    fn overflow(pc: i64) -> String {
+        format!("Arithmetic overflow at PC = {}", pc)
+    }
+
+    impl Machine {
+        fn rd(&self, pc: i64, address: i64) -> Result<i64, String> {
+            let address = self.dst(pc, address)?;
+            Ok(*self.mem.get(address).unwrap_or(&0))
+        }
+
+        fn dst(&self, pc: i64, address: i64) -> Result<usize, String> {
+            if address < 0 {
+                return Err(format!("Access to negative address {} at PC = {}", address, pc));
+            }
+            if address >= LIMIT {
+                return Err(format!(
+                    "Access to address {} above the limit at PC = {}",
+                    address, pc
+                ));
+            }
+            Ok(address as usize)
+        }
+
+        // The address of a Relative mode operand
+        fn rel(&self, pc: i64, operand: i64) -> Result<i64, String> {
+            self.rb.checked_add(operand).ok_or_else(|| overflow(pc))
+        }
+
+        fn wr(&mut self, address: usize, value: i64) {
+            if self.mem.len() <= address {
+                self.mem.resize(address + 1, 0);
+            }
+            self.mem[address] = value;
+            if address < CODE.len() && CODE[address] {
+                self.dirty = true;
+            }
+        }
+
+        // Interpret one instruction. Returns true if it is a halt.
+        fn step(
+            &mut self,
+            input: &mut dyn FnMut() -> Option<i64>,
+            output: &mut dyn FnMut(i64),
+        ) -> Result<bool, String> {
+            let pc = self.pc;
+            if pc < 0 || pc as usize >= self.mem.len() {
+                return Err(format!("Program counter is outside of memory at PC = {}", pc));
+            }
+            let word = self.mem[pc as usize];
+            let opcode = word % 100;
+            let arity = match opcode {
+                1 | 2 | 7 | 8 => 3,
+                5 | 6 => 2,
+                3 | 4 | 9 => 1,
+                99 => return Ok(true),
+                n => return Err(format!("Unknown opcode {} at PC = {}", n, pc)),
+            };
+            // Mode and operand of each parameter
+            let mut params = [(0, 0); 3];
+            let mut digits = word / 100;
+            for (idx, param) in params.iter_mut().enumerate().take(arity) {
+                *param = (digits % 10, self.rd(pc, pc + 1 + idx as i64)?);
+                digits /= 10;
+            }
+            let load = |m: &Self, idx: usize| match params[idx] {
+                (0, operand) => m.rd(pc, operand),
+                (1, operand) => Ok(operand),
+                (_, operand) => m.rd(pc, m.rel(pc, operand)?),
+            };
+            let dest = |m: &Self, idx: usize| match params[idx] {
+                (0, operand) => m.dst(pc, operand),
+                (1, _) => Err(format!("Attempted to write in immediate mode at PC = {}", pc)),
+                (_, operand) => m.dst(pc, m.rel(pc, operand)?),
+            };
+            match opcode {
+                1 | 2 | 7 | 8 => {
+                    let (a, b) = (load(self, 0)?, load(self, 1)?);
+                    let value = match opcode {
+                        1 => a.checked_add(b).ok_or_else(|| overflow(pc))?,
+                        2 => a.checked_mul(b).ok_or_else(|| overflow(pc))?,
+                        7 => (a < b) as i64,
+                        _ => (a == b) as i64,
+                    };
+                    let address = dest(self, 2)?;
+                    self.wr(address, value);
+                    self.pc += 4;
+                }
+                3 => {
+                    let address = dest(self, 0)?;
+                    let value = input().ok_or_else(|| {
+                        format!("Input was taken but none is left at PC = {}", pc)
+                    })?;
+                    self.wr(address, value);
+                    self.pc += 2;
+                }
+                4 => {
+                    output(load(self, 0)?);
+                    self.pc += 2;
+                }
+                5 | 6 => {
+                    let (test, target) = (load(self, 0)?, load(self, 1)?);
+                    self.pc = if (test != 0) == (opcode == 5) {
+                        target
+                    } else {
+                        pc + 3
+                    };
+                }
+                _ => {
+                    let offset = load(self, 0)?;
+                    self.rb = self.rel(pc, offset)?;
+                    self.pc += 2;
+                }
+            }
+            Ok(false)
+        }
+    }
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Computer, Program};
+    use std::fs;
+    use std::process::Command;
+
+    // What a Computer does with the program and input, in the same form
+    // as the test program below prints it
+    fn interpret(program: &[i64], input: &[i64]) -> String {
+        let mut computer = Computer::from(program.to_vec());
+        for &value in input {
+            computer.more_input(value);
+        }
+        let result = computer
+            .try_run()
+            .map(|_| ())
+            .map_err(|fault| fault.to_string());
+        let output = computer.output();
+        let result = result.map(|_| computer.memory().to_vec());
+        format!("{:?} {:?}", output, result)
+    }
+
+    fn day(number: usize) -> Option<Vec<i64>> {
+        let path = format!(
+            "{}/input/2019/day{}.txt",
+            env!("CARGO_MANIFEST_DIR"),
+            number
+        );
+        Program::load(path).ok().map(Vec::from)
+    }
+
+    #[test]
+    fn test_find_code() {
+        // A call to 9 that returns to 7, with data around the function
+        let program = [1101, 0, 7, 100, 1105, 1, 9, 99, 42, 1106, 0, 100, 43];
+        let addresses: Vec<usize> = find_code(&program)
+            .iter()
+            .enumerate()
+            .filter_map(|(address, op)| op.as_ref().map(|_| address))
+            .collect();
+        assert_eq!(addresses, vec![0, 4, 7, 9]);
+    }
+
+    // Transpile a set of programs, compile them with rustc and check that
+    // they behave like the Computer
+    #[test]
+    fn test_against_computer() {
+        let mut cases: Vec<(Vec<i64>, Vec<i64>)> = vec![
+            (vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50], vec![]),
+            (
+                vec![
+                    109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+                ],
+                vec![],
+            ),
+            (vec![104, 1_125_899_906_842_624, 99], vec![]),
+            // Writes a halt over its own code
+            (vec![1101, 90, 9, 6, 3, 9, 1105, 1, 0, 0], vec![5]),
+            (vec![3, 0, 4, 0, 99], vec![]),
+            (vec![1105, 1, 4, 99, 1106, 0, -2], vec![]),
+            // Faults instead of overflowing, in transpiled code and when
+            // interpreted after changing its own code
+            (vec![1102, 1 << 40, 1 << 40, 0, 99], vec![]),
+            (vec![109, i64::MAX, 109, 1, 99], vec![]),
+            (vec![1101, 0, 1 << 40, 5, 1102, 0, 1 << 40, 0, 99], vec![]),
+            // Writes far past the end of memory
+            (vec![1101, 1, 1, 1 << 30, 99], vec![]),
+            (vec![109, 1 << 30, 21101, 1, 1, 0, 99], vec![]),
+        ];
+        let compare8 = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        for input in 7..=9 {
+            cases.push((compare8.clone(), vec![input]));
+        }
+        if let Some(mut program) = day(2) {
+            program[1] = 12;
+            program[2] = 2;
+            cases.push((program, vec![]));
+        }
+        if let Some(program) = day(5) {
+            cases.push((program.clone(), vec![1]));
+            cases.push((program, vec![5]));
+        }
+        if let Some(program) = day(9) {
+            cases.push((program, vec![1]));
+        }
+        if let Some(program) = day(19) {
+            for &(x, y) in &[(0, 0), (10, 12), (30, 40), (49, 3)] {
+                cases.push((program.clone(), vec![x, y]));
+            }
+        }
+
+        let mut source = String::new();
+        let mut main = String::from("fn main() {\n");
+        for (idx, (program, input)) in cases.iter().enumerate() {
+            source += &transpile(program, &format!("case{}", idx));
+            main += &format!(
+                "    let mut input = vec!{:?}.into_iter();\n    \
+                 let mut output = Vec::new();\n    \
+                 let result = case{}::run(&mut || input.next(), &mut |v| output.push(v));\n    \
+                 println!(\"{{:?}} {{:?}}\", output, result);\n",
+                input, idx
+            );
+        }
+        source += &main;
+        source += "}\n";
+
+        let dir = std::env::temp_dir().join(format!("intcode-aot-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("cases.rs");
+        let binary = dir.join("cases");
+        fs::write(&file, source).unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+        let status = Command::new(rustc)
+            .args(["--edition", "2018", "-o"])
+            .arg(&binary)
+            .arg(&file)
+            .status()
+            .expect("Could not run rustc");
+        assert!(status.success());
+        let run = Command::new(&binary).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let stdout = String::from_utf8(run.stdout).unwrap();
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), cases.len());
+        for ((program, input), line) in cases.iter().zip(lines) {
+            assert_eq!(line, interpret(program, input));
+        }
+    }
+}
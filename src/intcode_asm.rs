@@ -1,7 +1,13 @@
+use crate::intcode::{builtin, InstructionSet, IsaProfile, Mode};
+use std::collections::BTreeSet;
+
 #[derive(Debug, Default)]
 pub struct Debugger {
     memory: Vec<i64>,
     cursor: usize,
+    isa: InstructionSet,
+    // Never show a comparison fused with the jump after it
+    unfused: bool,
 }
 
 impl From<Vec<i64>> for Debugger {
@@ -21,42 +27,122 @@ impl Debugger {
         }
     }
 
+    // Create a Debugger that also knows the custom opcodes of the set
+    pub fn with_instruction_set(memory: Vec<i64>, isa: InstructionSet) -> Self {
+        Self {
+            memory,
+            isa,
+            ..Default::default()
+        }
+    }
+
+    // Show opcodes and modes from later revisions than <profile> as data
+    pub fn set_isa_profile(&mut self, profile: IsaProfile) {
+        self.isa.set_profile(profile);
+    }
+
     pub fn assembly(&mut self) -> String {
+        self.annotated_assembly(|_| String::new())
+    }
+
+    // Like assembly, with a note in front of every instruction, such as
+    // how often it ran. The note gets the address of the instruction.
+    // Notes should all have the same width; the data segment is indented
+    // to match.
+    pub fn annotated_assembly<F: Fn(usize) -> String>(&mut self, note: F) -> String {
         let mut result = String::new();
         // Print code segment
         while self.cursor < self.memory.len() {
+            let start = self.cursor;
             let delta = self.op_length();
-            result += &format!("{:4}: ", self.cursor);
             let optext = self.op_to_string();
             if optext.starts_with("data") {
                 // Data segment
                 break;
             }
-            result += &optext;
+            // A fused comparison and jump comes back as two lines, each
+            // starting with its own address
+            let mut lines = optext.lines();
+            let first = lines.next().unwrap_or_default();
+            result += &format!("{}{:4}: {}\n", note(start), start, first);
+            for line in lines {
+                let address = line.split(':').next().unwrap_or_default().trim();
+                let address = address.parse().unwrap_or(start);
+                result += &format!("{}{}\n", note(address), line);
+            }
             self.cursor += delta;
         }
         // Print data segment
-
+        let blank = " ".repeat(note(self.cursor).len());
+        if self.cursor < self.memory.len() {
+            result += &format!("{}{:4}: ", blank, self.cursor);
+        }
         while self.cursor < self.memory.len() {
             result += &format!("{:7} ", self.memory[self.cursor]);
             self.cursor += 1;
             if self.cursor % 8 == 0 {
-                result += &format!("\n{:4}: ", self.cursor);
+                result += &format!("\n{}{:4}: ", blank, self.cursor);
             }
         }
         result + "\n"
     }
 
-    fn op_length(&self) -> usize {
-        match self.opcode() {
-            1 | 2 | 7 | 8 => 4,
-            3 | 4 | 9 => 2,
-            5 | 6 => 3,
-            _ => 1,
+    // Disassemble up to <count> instructions starting at <start>. Unlike
+    // assembly, this does not stop at the first value that is not an
+    // instruction, so it can be used on any part of the program.
+    pub fn disassemble(&mut self, start: usize, count: usize) -> String {
+        let len = self.memory.len();
+        // Room for the operands of a (fused) instruction at the very end
+        self.memory.resize(len + 8, 0);
+        self.cursor = start;
+        let mut result = String::new();
+        for _ in 0..count {
+            if self.cursor >= len {
+                break;
+            }
+            let delta = self.op_length();
+            result += &format!("{:4}: ", self.cursor);
+            result += &self.op_to_string();
+            self.cursor += delta;
+        }
+        self.memory.truncate(len);
+        result
+    }
+
+    // The instruction at <address> on one line, without fusing a
+    // comparison with the jump after it
+    pub fn instruction(&mut self, address: usize) -> String {
+        self.unfused = true;
+        let result = self.disassemble(address, 1);
+        self.unfused = false;
+        result
+    }
+
+    // Like annotated_assembly, but lists the instructions at <code>, one
+    // line per address, and skips everything else. Useful when data is
+    // mixed in with the code.
+    pub fn annotated_listing<F: Fn(usize) -> String>(
+        &mut self,
+        code: &BTreeSet<usize>,
+        note: F,
+    ) -> String {
+        let len = self.memory.len();
+        let mut result = String::new();
+        for &address in code.iter().filter(|&&address| address < len) {
+            result += &note(address);
+            result += &self.instruction(address);
         }
+        result
+    }
+
+    fn op_length(&self) -> usize {
+        self.isa.arity(self.opcode()).map_or(1, |arity| arity + 1)
     }
 
     fn op_to_string(&mut self) -> String {
+        if !self.in_profile() {
+            return format!("data {}\n", self.opcode());
+        }
         (match self.opcode() {
             1 => self.bin_op("+"),
             2 => self.bin_op("*"),
@@ -68,6 +154,7 @@ impl Debugger {
             8 => self.bin_op("=="),
             9 => self.relative_base_increment(),
             99 => "halt".to_owned(),
+            n if self.isa.is_custom(n) => self.custom_op(n),
             n => format!("data {}", n),
         }) + "\n"
     }
@@ -79,6 +166,16 @@ impl Debugger {
         self.memory[self.cursor] % 100
     }
 
+    // Whether the instruction set has the opcode of the current
+    // instruction and all the modes of its parameters
+    fn in_profile(&self) -> bool {
+        let profile = self.isa.profile();
+        let mask = self.mask();
+        self.isa
+            .arity(self.opcode())
+            .is_some_and(|arity| (0..arity).all(|idx| profile.allows_mode(*mask.get(idx))))
+    }
+
     // Returns the mask of the current instruction, in parameter order
     fn mask(&self) -> Mask {
         Mask::new(self.memory[self.cursor])
@@ -89,11 +186,7 @@ impl Debugger {
     }
 
     fn param_to_string_mode(&self, offset: usize, mode: Mode) -> String {
-        match mode {
-            Mode::Immediate => return format!("{}", self.memory[self.cursor + offset + 1]),
-            Mode::Position => return format!("*{}", self.memory[self.cursor + offset + 1]),
-            Mode::Relative => return format!("rb[{}]", self.memory[self.cursor + offset + 1]),
-        }
+        operand_to_string(self.memory[self.cursor + offset + 1], mode)
     }
 
     fn param(&self, offset: usize) -> i64 {
@@ -118,6 +211,7 @@ impl Debugger {
             return format!("{} := {}", self.param_to_string(2), self.param_to_string(0));
         }
         if op == "=="
+            && !self.unfused
             && (self.memory[self.cursor + 4] % 100 == 5 || self.memory[self.cursor + 4] % 100 == 6)
         {
             // Possible inline of comparison-then-if
@@ -180,11 +274,75 @@ impl Debugger {
         format!("output({})", self.param_to_string(0))
     }
 
+    // Registered opcode: name(args), with the destination in front if it
+    // writes one
+    fn custom_op(&self, opcode: Opcode) -> String {
+        let write = self.isa.write_param(opcode);
+        let arity = self.isa.arity(opcode).unwrap_or(0);
+        let args: Vec<String> = (0..arity)
+            .filter(|&idx| Some(idx) != write)
+            .map(|idx| self.param_to_string(idx))
+            .collect();
+        let call = format!(
+            "{}({})",
+            self.isa.name(opcode).unwrap_or("?"),
+            args.join(", ")
+        );
+        match write {
+            Some(idx) => format!("{} := {}", self.param_to_string(idx), call),
+            None => call,
+        }
+    }
+
     fn relative_base_increment(&self) -> String {
         format!("rb += {}", self.param_to_string(0))
     }
 }
 
+// Render an operand the way the listing shows it: a plain number in
+// Immediate mode, *addr in Position mode and rb[n] in Relative mode.
+pub fn operand_to_string(operand: i64, mode: Mode) -> String {
+    match mode {
+        Mode::Immediate => format!("{}", operand),
+        Mode::Position => format!("*{}", operand),
+        Mode::Relative => format!("rb[{}]", operand),
+    }
+}
+
+// One instruction, decoded the way the listing reads it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Op {
+    pub opcode: i64,
+    // Operand and mode of each parameter
+    pub params: Vec<(i64, Mode)>,
+}
+
+impl Op {
+    // Decode the instruction at <address>. Returns None if there is no
+    // known opcode there. Operands past the end of memory read as 0, like
+    // they do for the Computer.
+    pub fn decode(memory: &[i64], address: usize) -> Option<Op> {
+        let word = *memory.get(address)?;
+        let opcode = word % 100;
+        let (_, arity, _) = builtin(opcode)?;
+        let mask = Mask::new(word);
+        let params = (0..arity)
+            .map(|idx| (*memory.get(address + idx + 1).unwrap_or(&0), *mask.get(idx)))
+            .collect();
+        Some(Op { opcode, params })
+    }
+
+    // Number of memory cells taken by the instruction
+    pub fn length(&self) -> usize {
+        self.params.len() + 1
+    }
+
+    // Index of the parameter the instruction writes to, if any
+    pub fn write_param(&self) -> Option<usize> {
+        builtin(self.opcode).and_then(|(_, _, write)| write)
+    }
+}
+
 type Opcode = i64;
 struct Mask(Vec<Mode>);
 
@@ -207,10 +365,3 @@ impl Mask {
         self.0.get(idx).unwrap_or(&Mode::Position)
     }
 }
-
-#[derive(Debug, Copy, Clone, PartialEq)]
-enum Mode {
-    Immediate,
-    Position,
-    Relative,
-}
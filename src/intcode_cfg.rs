@@ -0,0 +1,326 @@
+use crate::intcode::Mode;
+use crate::intcode_aot::find_code;
+use crate::intcode_asm::{operand_to_string, Debugger, Op};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Where a jump goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Static(usize),
+    // Computed at run time, like the rb[0] that holds a return address.
+    // The operand and its mode.
+    Indirect(i64, Mode),
+}
+
+impl Target {
+    fn new((operand, mode): (i64, Mode)) -> Self {
+        match mode {
+            Mode::Immediate if operand >= 0 => Target::Static(operand as usize),
+            _ => Target::Indirect(operand, mode),
+        }
+    }
+}
+
+// How control leaves a basic block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    // Runs on into the instruction at this address
+    Next(usize),
+    Goto(Target),
+    // Jumps to the target or runs on into next, depending on the test
+    Branch { target: Target, next: usize },
+    Halt,
+}
+
+impl Exit {
+    // Where control can go, as far as is known without running the code
+    pub fn successors(&self) -> Vec<usize> {
+        match *self {
+            Exit::Next(next) | Exit::Goto(Target::Static(next)) => vec![next],
+            Exit::Branch {
+                target: Target::Static(target),
+                next,
+            } => vec![target, next],
+            Exit::Branch { next, .. } => vec![next],
+            Exit::Goto(_) | Exit::Halt => vec![],
+        }
+    }
+}
+
+// Instructions that only run in sequence: control enters at the first
+// one and leaves after the last one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    // The instructions with their addresses, including a jump at the end
+    pub ops: Vec<(usize, Op)>,
+    pub exit: Exit,
+}
+
+impl Block {
+    // The address after the last instruction
+    pub fn end(&self) -> usize {
+        self.ops
+            .last()
+            .map_or(self.start, |(address, op)| address + op.length())
+    }
+
+    // The addresses the block can continue at that are known without
+    // running it. A branch has its target first.
+    pub fn successors(&self) -> Vec<usize> {
+        self.exit.successors()
+    }
+
+    // Whether the block ends in a jump to a computed address
+    pub fn is_indirect(&self) -> bool {
+        match self.exit {
+            Exit::Goto(target) | Exit::Branch { target, .. } => {
+                matches!(target, Target::Indirect(..))
+            }
+            _ => false,
+        }
+    }
+}
+
+// How the instruction changes control flow, or None if it always runs on
+// into the next one
+fn exit_of(op: &Op, next: usize) -> Option<Exit> {
+    match op.opcode {
+        5 | 6 => {
+            let target = Target::new(op.params[1]);
+            match op.params[0] {
+                (test, Mode::Immediate) if (test != 0) == (op.opcode == 5) => {
+                    Some(Exit::Goto(target))
+                }
+                // Never jumps
+                (_, Mode::Immediate) => None,
+                _ => Some(Exit::Branch { target, next }),
+            }
+        }
+        99 => Some(Exit::Halt),
+        _ => None,
+    }
+}
+
+// The code of a program split into basic blocks. Only the code that
+// find_code reaches is included: from address 0, through jumps to fixed
+// addresses, and after jumps and halts if the address appears in the
+// program as a return address would.
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    memory: Vec<i64>,
+    blocks: BTreeMap<usize, Block>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(program: &[i64]) -> Self {
+        let code = find_code(program);
+        let is_code = |address: usize| code.get(address).is_some_and(Option::is_some);
+        // Blocks start at 0, at jump targets and after control flow
+        let mut leaders: BTreeSet<usize> = BTreeSet::new();
+        leaders.insert(0);
+        for (address, op) in code.iter().enumerate() {
+            if let Some(op) = op {
+                let next = address + op.length();
+                if let Some(exit) = exit_of(op, next) {
+                    leaders.extend(exit.successors());
+                    leaders.insert(next);
+                }
+            }
+        }
+        leaders.retain(|&address| is_code(address));
+
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            let mut ops = Vec::new();
+            let mut address = start;
+            let exit = loop {
+                let op = code[address].clone().expect("blocks only contain code");
+                let next = address + op.length();
+                let exit = exit_of(&op, next);
+                ops.push((address, op));
+                match exit {
+                    Some(exit) => break exit,
+                    None if leaders.contains(&next) || !is_code(next) => break Exit::Next(next),
+                    None => address = next,
+                }
+            };
+            blocks.insert(start, Block { start, ops, exit });
+        }
+        ControlFlowGraph {
+            memory: program.to_vec(),
+            blocks,
+        }
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: usize) -> Option<&Block> {
+        self.blocks.get(&start)
+    }
+
+    // The blocks that the block at <start> can continue at
+    pub fn successors(&self, start: usize) -> Vec<usize> {
+        self.blocks.get(&start).map_or(Vec::new(), |block| {
+            let successors = block.successors().into_iter();
+            successors
+                .filter(|address| self.blocks.contains_key(address))
+                .collect()
+        })
+    }
+
+    // The blocks that can continue at the block at <start>, not counting
+    // indirect jumps
+    pub fn predecessors(&self, start: usize) -> Vec<usize> {
+        self.blocks()
+            .filter(|block| block.successors().contains(&start))
+            .map(|block| block.start)
+            .collect()
+    }
+
+    // The listing of a block, one line per instruction
+    pub fn listing(&self, block: &Block) -> Vec<String> {
+        let mut debugger = Debugger::new(self.memory.clone());
+        let text = debugger.disassemble(block.start, block.ops.len());
+        // A comparison fused with a jump just past the block shows both
+        text.lines()
+            .filter(|line| {
+                let address = line.split(':').next().unwrap_or_default().trim();
+                address
+                    .parse()
+                    .is_ok_and(|address: usize| address < block.end())
+            })
+            .map(|line| line.to_owned())
+            .collect()
+    }
+
+    // One line per block: its start and where it continues. For example
+    // `  12 -> 40, 19` for a branch, with the jump target first.
+    pub fn adjacency(&self) -> String {
+        let mut result = String::new();
+        for block in self.blocks() {
+            let mut targets: Vec<String> = block
+                .successors()
+                .iter()
+                .map(|address| {
+                    if self.blocks.contains_key(address) {
+                        address.to_string()
+                    } else {
+                        format!("{} (not code)", address)
+                    }
+                })
+                .collect();
+            match block.exit {
+                Exit::Halt => targets.push("halt".to_owned()),
+                Exit::Goto(Target::Indirect(operand, mode))
+                | Exit::Branch {
+                    target: Target::Indirect(operand, mode),
+                    ..
+                } => targets.push(format!("indirect {}", operand_to_string(operand, mode))),
+                _ => {}
+            }
+            result += &format!("{:4} -> {}\n", block.start, targets.join(", "));
+        }
+        result
+    }
+
+    // The graph in Graphviz DOT, with the listing of every block. Blocks
+    // ending in an indirect jump are dashed, and the edge a branch takes
+    // when it jumps is labelled.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph intcode {\n");
+        dot += "    node [shape=box, fontname=\"monospace\"];\n";
+        for block in self.blocks() {
+            let label: String = self
+                .listing(block)
+                .iter()
+                .map(|line| line.replace('\\', "\\\\").replace('"', "\\\"") + "\\l")
+                .collect();
+            let style = if block.is_indirect() {
+                ", style=dashed"
+            } else {
+                ""
+            };
+            dot += &format!("    b{} [label=\"{}\"{}];\n", block.start, label, style);
+        }
+        for block in self.blocks() {
+            let branch = matches!(
+                block.exit,
+                Exit::Branch {
+                    target: Target::Static(_),
+                    ..
+                }
+            );
+            for (idx, successor) in block.successors().into_iter().enumerate() {
+                if !self.blocks.contains_key(&successor) {
+                    continue;
+                }
+                let label = if branch && idx == 0 {
+                    " [label=\"jump\"]"
+                } else {
+                    ""
+                };
+                dot += &format!("    b{} -> b{}{};\n", block.start, successor, label);
+            }
+        }
+        dot + "}\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::Program;
+
+    #[test]
+    fn test_blocks() {
+        // 0: rb += 100
+        // 2: rb[0] := 9; goto 11      call 11, returning to 9
+        // 9: halt
+        // 11: *10 := input(); if *10 == 0 { goto 21 }
+        // 16: output(*10); goto 11
+        // 21: goto rb[0]              return
+        let program = [
+            109, 100, 21101, 9, 0, 0, 1105, 1, 11, 99, 0, 3, 10, 1006, 10, 21, 4, 10, 1105, 1, 11,
+            2105, 1, 0,
+        ];
+        let cfg = ControlFlowGraph::new(&program);
+        let starts: Vec<usize> = cfg.blocks().map(|block| block.start).collect();
+        assert_eq!(starts, vec![0, 9, 11, 16, 21]);
+        assert_eq!(cfg.successors(11), vec![21, 16]);
+        assert_eq!(cfg.predecessors(11), vec![0, 16]);
+        assert!(cfg.block(21).unwrap().is_indirect());
+        assert_eq!(
+            cfg.adjacency(),
+            "   0 -> 11\n   9 -> halt\n  11 -> 21, 16\n  16 -> 11\n  21 -> indirect rb[0]\n"
+        );
+        assert_eq!(
+            cfg.listing(cfg.block(11).unwrap()),
+            vec!["  11: *10 := input()", "  13: if *10 == 0 { goto 21 }"]
+        );
+        let dot = cfg.to_dot();
+        assert!(dot.contains("    b11 -> b21 [label=\"jump\"];\n    b11 -> b16;\n"));
+        assert!(
+            dot.contains("    b21 [label=\"  21: if 1 != 0 { goto rb[0] }\\l\", style=dashed];\n")
+        );
+    }
+
+    #[test]
+    fn test_day25() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/input/2019/day25.txt");
+        let program = Program::load(path).unwrap();
+        let cfg = ControlFlowGraph::new(&program);
+        // Blocks do not overlap and every instruction found is in one
+        let mut end = 0;
+        for block in cfg.blocks() {
+            assert!(block.start >= end, "{:?}", block);
+            end = block.end();
+        }
+        let ops: usize = cfg.blocks().map(|block| block.ops.len()).sum();
+        let code = find_code(&program).iter().filter(|op| op.is_some()).count();
+        assert_eq!(ops, code);
+        assert!(cfg.blocks().any(Block::is_indirect));
+    }
+}
@@ -0,0 +1,887 @@
+use crate::intcode::Mode;
+use crate::intcode_asm::Op;
+use crate::intcode_cfg::{Block, ControlFlowGraph, Exit, Target};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Pseudo-code before it is laid out as text
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stmt {
+    Line(String),
+    // The start of the code of a block, where a label goes if there is a
+    // goto to it
+    Block(usize),
+    If {
+        cond: String,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    While {
+        cond: String,
+        body: Vec<Stmt>,
+    },
+    Loop(Vec<Stmt>),
+    // Runs the body, then goes round again while the condition holds
+    DoWhile {
+        body: Vec<Stmt>,
+        cond: String,
+    },
+    Break,
+    Continue,
+    Goto(usize),
+    Return,
+    Halt,
+    // Control runs into an address that is not code
+    NotCode(usize),
+}
+
+impl Stmt {
+    // Whether control never goes on to the next statement
+    fn is_exit(&self) -> bool {
+        matches!(
+            self,
+            Stmt::Break
+                | Stmt::Continue
+                | Stmt::Goto(_)
+                | Stmt::Return
+                | Stmt::Halt
+                | Stmt::NotCode(_)
+        )
+    }
+}
+
+// A loop found from a jump back to its header
+#[derive(Debug, Clone)]
+struct Loop {
+    body: BTreeSet<usize>,
+    // Where the loop is left for, if it is left at all
+    follow: Option<usize>,
+}
+
+// Stands for the end of the function when computing post-dominators
+const EXIT: usize = usize::MAX;
+
+// One function: the blocks reachable from its entry, with calls stepped
+// over
+struct Function<'a> {
+    cfg: &'a ControlFlowGraph,
+    // Block that makes a call, and where it calls
+    calls: &'a BTreeMap<usize, Target>,
+    // Cells that are read, see decompile
+    reads: &'a [(i64, Mode)],
+    main: bool,
+    nodes: BTreeSet<usize>,
+    loops: BTreeMap<usize, Loop>,
+    ipdom: BTreeMap<usize, usize>,
+    emitted: BTreeSet<usize>,
+    // The headers of the loops being emitted, innermost last
+    context: Vec<usize>,
+}
+
+impl<'a> Function<'a> {
+    fn new(
+        cfg: &'a ControlFlowGraph,
+        calls: &'a BTreeMap<usize, Target>,
+        reads: &'a [(i64, Mode)],
+        entry: usize,
+        main: bool,
+    ) -> Self {
+        let mut function = Function {
+            cfg,
+            calls,
+            reads,
+            main,
+            nodes: BTreeSet::new(),
+            loops: BTreeMap::new(),
+            ipdom: BTreeMap::new(),
+            emitted: BTreeSet::new(),
+            context: Vec::new(),
+        };
+        function.find_loops(entry);
+        function.find_ipdoms();
+        function
+    }
+
+    fn successors(&self, node: usize) -> Vec<usize> {
+        match self.calls.get(&node) {
+            Some(_) => {
+                let back = self.cfg.block(node).map_or(node, Block::end);
+                self.cfg
+                    .block(back)
+                    .map(|block| block.start)
+                    .into_iter()
+                    .collect()
+            }
+            None => self.cfg.successors(node),
+        }
+    }
+
+    // Depth-first search from the entry. A jump to a block that is still
+    // on the stack makes a loop; its body is everything that reaches the
+    // jump without going through the header.
+    fn find_loops(&mut self, entry: usize) {
+        let mut back_edges = Vec::new();
+        let mut on_stack = BTreeSet::new();
+        let mut stack = vec![(entry, 0)];
+        self.nodes.insert(entry);
+        on_stack.insert(entry);
+        while let Some(&mut (node, ref mut idx)) = stack.last_mut() {
+            let successors = self.successors(node);
+            match successors.get(*idx) {
+                Some(&next) => {
+                    *idx += 1;
+                    if on_stack.contains(&next) {
+                        back_edges.push((node, next));
+                    } else if self.nodes.insert(next) {
+                        on_stack.insert(next);
+                        stack.push((next, 0));
+                    }
+                }
+                None => {
+                    on_stack.remove(&node);
+                    stack.pop();
+                }
+            }
+        }
+        let mut predecessors: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for &node in &self.nodes {
+            for next in self.successors(node) {
+                predecessors.entry(next).or_default().push(node);
+            }
+        }
+        let mut bodies: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        for (from, header) in back_edges {
+            let body = bodies.entry(header).or_default();
+            body.insert(header);
+            let mut todo = vec![from];
+            while let Some(node) = todo.pop() {
+                if body.insert(node) {
+                    todo.extend(predecessors.get(&node).into_iter().flatten());
+                }
+            }
+        }
+        for (header, body) in bodies {
+            let exits = |node: usize| -> Vec<usize> {
+                let successors = self.successors(node).into_iter();
+                successors.filter(|next| !body.contains(next)).collect()
+            };
+            // Prefer leaving from the header, like a while loop does
+            let follow = exits(header)
+                .into_iter()
+                .min()
+                .or_else(|| body.iter().flat_map(|&node| exits(node)).min());
+            self.loops.insert(header, Loop { body, follow });
+        }
+    }
+
+    // Immediate post-dominators, where the arms of a branch meet again
+    fn find_ipdoms(&mut self) {
+        let all: BTreeSet<usize> = self.nodes.iter().copied().chain(Some(EXIT)).collect();
+        let mut pdom: BTreeMap<usize, BTreeSet<usize>> =
+            self.nodes.iter().map(|&node| (node, all.clone())).collect();
+        pdom.insert(EXIT, Some(EXIT).into_iter().collect());
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in self.nodes.iter().rev() {
+                let mut successors = self.successors(node);
+                if successors.is_empty() {
+                    successors.push(EXIT);
+                }
+                let mut set = successors
+                    .iter()
+                    .map(|next| pdom[next].clone())
+                    .reduce(|a, b| a.intersection(&b).copied().collect())
+                    .unwrap_or_default();
+                set.insert(node);
+                if set != pdom[&node] {
+                    pdom.insert(node, set);
+                    changed = true;
+                }
+            }
+        }
+        for &node in &self.nodes {
+            let mut strict = pdom[&node].clone();
+            strict.remove(&node);
+            let ipdom = strict.iter().find(|&&other| pdom[&other] == strict);
+            if let Some(&ipdom) = ipdom.filter(|&&other| other != EXIT) {
+                self.ipdom.insert(node, ipdom);
+            }
+        }
+    }
+
+    // How to get to <node> if it can not be emitted here: continuing or
+    // leaving the innermost loop, or a goto
+    fn exit_stmt(&self, node: usize) -> Option<Stmt> {
+        if let Some(header) = self.context.last() {
+            let inner = &self.loops[header];
+            if node == *header {
+                return Some(Stmt::Continue);
+            }
+            if Some(node) == inner.follow {
+                return Some(Stmt::Break);
+            }
+            if !inner.body.contains(&node) {
+                return Some(Stmt::Goto(node));
+            }
+        }
+        if self.emitted.contains(&node) {
+            return Some(Stmt::Goto(node));
+        }
+        None
+    }
+
+    // Emit the code from <node> on, up to <stop>
+    fn region(&mut self, mut node: usize, stop: Option<usize>, out: &mut Vec<Stmt>) {
+        loop {
+            if Some(node) == stop {
+                return;
+            }
+            if let Some(stmt) = self.exit_stmt(node) {
+                out.push(stmt);
+                return;
+            }
+            let next = if self.loops.contains_key(&node) {
+                self.emit_loop(node, out)
+            } else {
+                self.emit_block(node, out)
+            };
+            match next {
+                Some(next) => node = next,
+                None => return,
+            }
+        }
+    }
+
+    fn emit_loop(&mut self, header: usize, out: &mut Vec<Stmt>) -> Option<usize> {
+        let follow = self.loops[&header].follow;
+        self.context.push(header);
+        let block = self.cfg.block(header).expect("loop headers are blocks");
+        let (statements, conditions) = self.statements(block);
+        let mut body = Vec::new();
+        // A header that only tests whether to leave makes a while loop
+        match (block.exit, conditions) {
+            (Exit::Branch { target, next }, Some((jump, fall)))
+                if statements.is_empty() && follow.is_some() =>
+            {
+                let inside = |other| self.loops[&header].body.contains(&other);
+                let (cond, first) = match target {
+                    Target::Static(target) if Some(target) == follow && inside(next) => {
+                        (fall, next)
+                    }
+                    Target::Static(target) if Some(next) == follow && inside(target) => {
+                        (jump, target)
+                    }
+                    _ => return self.emit_loop_body(header, follow, out),
+                };
+                self.emitted.insert(header);
+                body.push(Stmt::Block(header));
+                self.region(first, None, &mut body);
+                strip_continue(&mut body);
+                out.push(Stmt::While { cond, body });
+                self.context.pop();
+                follow
+            }
+            _ => self.emit_loop_body(header, follow, out),
+        }
+    }
+
+    // Emit a loop as `loop { ... }`, leaving it with break, or as
+    // `do { ... } while c` if it only goes round again from the end
+    fn emit_loop_body(
+        &mut self,
+        header: usize,
+        follow: Option<usize>,
+        out: &mut Vec<Stmt>,
+    ) -> Option<usize> {
+        let mut body = Vec::new();
+        if let Some(next) = self.emit_block(header, &mut body) {
+            self.region(next, None, &mut body);
+        }
+        match take_do_while(&mut body) {
+            Some(cond) => out.push(Stmt::DoWhile { body, cond }),
+            None => {
+                strip_continue(&mut body);
+                out.push(Stmt::Loop(body));
+            }
+        }
+        self.context.pop();
+        follow
+    }
+
+    // Emit one block. Returns where the code goes on, if it does.
+    fn emit_block(&mut self, node: usize, out: &mut Vec<Stmt>) -> Option<usize> {
+        self.emitted.insert(node);
+        let block = self.cfg.block(node).expect("only blocks are emitted");
+        out.push(Stmt::Block(node));
+        let (statements, conditions) = self.statements(block);
+        out.extend(statements.into_iter().map(Stmt::Line));
+        if let Some(&callee) = self.calls.get(&node) {
+            let callee = match callee {
+                Target::Static(address) => format!("f{}", address),
+                Target::Indirect(operand, mode) => name(operand, mode),
+            };
+            out.push(Stmt::Line(format!("call {}", callee)));
+            return self.successors(node).first().copied();
+        }
+        match block.exit {
+            Exit::Next(next) | Exit::Goto(Target::Static(next)) => self.go_on(next, out),
+            Exit::Goto(Target::Indirect(operand, mode)) => {
+                out.push(self.indirect(operand, mode));
+                None
+            }
+            Exit::Halt => {
+                out.push(Stmt::Halt);
+                None
+            }
+            Exit::Branch { target, next } => {
+                let (jump, fall) = conditions.expect("branches have conditions");
+                let target = match target {
+                    Target::Static(target) => target,
+                    Target::Indirect(operand, mode) => {
+                        let then = vec![self.indirect(operand, mode)];
+                        let otherwise = Vec::new();
+                        out.push(Stmt::If {
+                            cond: jump,
+                            then,
+                            otherwise,
+                        });
+                        return self.go_on(next, out);
+                    }
+                };
+                // A side that is not code can only be pointed out
+                if self.cfg.block(target).is_none() {
+                    out.push(if_only(jump, Stmt::NotCode(target)));
+                    return self.go_on(next, out);
+                }
+                if self.cfg.block(next).is_none() {
+                    out.push(if_only(fall, Stmt::NotCode(next)));
+                    return Some(target);
+                }
+                // Early exits from a loop, or jumps to code emitted before
+                if let Some(stmt) = self.exit_stmt(target) {
+                    out.push(if_only(jump, stmt));
+                    return Some(next);
+                }
+                if let Some(stmt) = self.exit_stmt(next) {
+                    out.push(if_only(fall, stmt));
+                    return Some(target);
+                }
+                let merge = self.ipdom.get(&node).copied();
+                let (mut then, mut otherwise) = (Vec::new(), Vec::new());
+                self.region(next, merge, &mut then);
+                self.region(target, merge, &mut otherwise);
+                let (cond, then, otherwise) = if then.iter().all(|s| matches!(s, Stmt::Block(_))) {
+                    (jump, otherwise, then)
+                } else {
+                    (fall, then, otherwise)
+                };
+                // No else is needed after an arm that does not come back
+                if then.last().is_some_and(Stmt::is_exit) {
+                    out.push(Stmt::If {
+                        cond,
+                        then,
+                        otherwise: Vec::new(),
+                    });
+                    out.extend(otherwise);
+                } else {
+                    out.push(Stmt::If {
+                        cond,
+                        then,
+                        otherwise,
+                    });
+                }
+                merge
+            }
+        }
+    }
+
+    // Where the code goes on after a block, unless that is not code
+    fn go_on(&self, next: usize, out: &mut Vec<Stmt>) -> Option<usize> {
+        if self.cfg.block(next).is_none() {
+            out.push(Stmt::NotCode(next));
+            return None;
+        }
+        Some(next)
+    }
+
+    fn indirect(&self, operand: i64, mode: Mode) -> Stmt {
+        if !self.main && mode == Mode::Relative {
+            Stmt::Return
+        } else {
+            Stmt::Line(format!("goto {}", name(operand, mode)))
+        }
+    }
+
+    // The statements of the block, without the jump at the end and the
+    // return address stored by a call. A comparison tested by the jump
+    // right after it becomes part of the conditions of the jump: when it
+    // jumps and when it does not. Its store is only left out if nothing
+    // else reads the cell.
+    fn statements(&self, block: &Block) -> (Vec<String>, Option<(String, String)>) {
+        let mut ops: Vec<&Op> = block.ops.iter().map(|(_, op)| op).collect();
+        if self.calls.contains_key(&block.start) {
+            let end = (block.end() as i64, Mode::Immediate);
+            ops.retain(|op| !(op.write_param().is_some() && op.params.contains(&end)));
+        }
+        let mut conditions = None;
+        if let Exit::Branch { .. } = block.exit {
+            let jump = ops.pop().expect("a branch ends the block");
+            let test = jump.params[0];
+            let read = self.reads.contains(&test);
+            // The condition can not use the operands after the store
+            // changed one of them
+            let fused = ops.last().filter(|op| {
+                is_compare(op) && op.params[2] == test && !(read && op.params[..2].contains(&test))
+            });
+            let (nonzero, zero) = match fused {
+                Some(compare) => {
+                    let (a, b) = (name_of(compare.params[0]), name_of(compare.params[1]));
+                    let (op, negated) = if compare.opcode == 7 {
+                        ("<", ">=")
+                    } else {
+                        ("==", "!=")
+                    };
+                    let conditions = (
+                        format!("{} {} {}", a, op, b),
+                        format!("{} {} {}", a, negated, b),
+                    );
+                    if !read {
+                        ops.pop();
+                    }
+                    conditions
+                }
+                None => {
+                    let test = name_of(test);
+                    (format!("{} != 0", test), format!("{} == 0", test))
+                }
+            };
+            conditions = Some(if jump.opcode == 5 {
+                (nonzero, zero)
+            } else {
+                (zero, nonzero)
+            });
+        }
+        (ops.into_iter().filter_map(statement).collect(), conditions)
+    }
+}
+
+fn if_only(cond: String, stmt: Stmt) -> Stmt {
+    Stmt::If {
+        cond,
+        then: vec![stmt],
+        otherwise: Vec::new(),
+    }
+}
+
+fn is_compare(op: &Op) -> bool {
+    op.opcode == 7 || op.opcode == 8
+}
+
+// The condition of a loop body that ends in `if c { continue } break`,
+// taking the test off the end. Not if the body continues anywhere else,
+// since that would then test the condition first.
+fn take_do_while(body: &mut Vec<Stmt>) -> Option<String> {
+    let n = body.len();
+    if n < 2 || body[n - 1] != Stmt::Break || body[..n - 2].iter().any(continues) {
+        return None;
+    }
+    match &body[n - 2] {
+        Stmt::If {
+            then, otherwise, ..
+        } if then[..] == [Stmt::Continue] && otherwise.is_empty() => {}
+        _ => return None,
+    }
+    body.pop();
+    match body.pop() {
+        Some(Stmt::If { cond, .. }) => Some(cond),
+        _ => None,
+    }
+}
+
+// Whether the statement goes round the loop it is in again
+fn continues(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Continue => true,
+        Stmt::If {
+            then, otherwise, ..
+        } => then.iter().chain(otherwise).any(continues),
+        _ => false,
+    }
+}
+
+// A loop body that ends by going round again does not need to say so
+fn strip_continue(body: &mut Vec<Stmt>) {
+    if body.last() == Some(&Stmt::Continue) {
+        body.pop();
+    }
+}
+
+// Memory cells at fixed addresses are variables, v and the address
+fn name(operand: i64, mode: Mode) -> String {
+    match mode {
+        Mode::Immediate => operand.to_string(),
+        Mode::Position => format!("v{}", operand),
+        Mode::Relative => format!("rb[{}]", operand),
+    }
+}
+
+fn name_of((operand, mode): (i64, Mode)) -> String {
+    name(operand, mode)
+}
+
+// One instruction that does not jump, or None for jumps that never jump
+// and halts
+fn statement(op: &Op) -> Option<String> {
+    let p = |idx: usize| name_of(op.params[idx]);
+    let is = |idx: usize, value: i64| op.params[idx] == (value, Mode::Immediate);
+    Some(match op.opcode {
+        1 if is(0, 0) => format!("{} := {}", p(2), p(1)),
+        1 if is(1, 0) => format!("{} := {}", p(2), p(0)),
+        2 if is(0, 1) => format!("{} := {}", p(2), p(1)),
+        2 if is(1, 1) => format!("{} := {}", p(2), p(0)),
+        1 => format!("{} := {} + {}", p(2), p(0), p(1)),
+        2 => format!("{} := {} * {}", p(2), p(0), p(1)),
+        7 => format!("{} := {} < {}", p(2), p(0), p(1)),
+        8 => format!("{} := {} == {}", p(2), p(0), p(1)),
+        3 => format!("{} := input()", p(0)),
+        4 => format!("output({})", p(0)),
+        9 => format!("rb += {}", p(0)),
+        _ => return None,
+    })
+}
+
+// Lay out statements, with labels only where there are gotos
+fn render(stmts: &[Stmt], depth: usize, labels: &BTreeSet<usize>, out: &mut String) {
+    let indent = "    ".repeat(depth);
+    let body = |stmts: &[Stmt], out: &mut String| render(stmts, depth + 1, labels, out);
+    for stmt in stmts {
+        match stmt {
+            Stmt::Line(line) => *out += &format!("{}{}\n", indent, line),
+            Stmt::Block(start) if labels.contains(start) => {
+                *out += &format!("{}L{}:\n", indent, start)
+            }
+            Stmt::Block(_) => {}
+            Stmt::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                // An arm of only unlabelled blocks has no text
+                let mut rest = String::new();
+                body(otherwise, &mut rest);
+                // A lone exit fits on one line, unless it is a comment
+                let lone = then.len() == 1 && then[0].is_exit();
+                if rest.is_empty() && lone && !matches!(then[0], Stmt::NotCode(_)) {
+                    let mut inline = String::new();
+                    render(then, 0, labels, &mut inline);
+                    *out += &format!("{}if {} {{ {} }}\n", indent, cond, inline.trim_end());
+                    continue;
+                }
+                *out += &format!("{}if {} {{\n", indent, cond);
+                body(then, out);
+                if !rest.is_empty() {
+                    *out += &format!("{}}} else {{\n", indent);
+                    *out += &rest;
+                }
+                *out += &format!("{}}}\n", indent);
+            }
+            Stmt::While { cond, body: stmts } => {
+                *out += &format!("{}while {} {{\n", indent, cond);
+                body(stmts, out);
+                *out += &format!("{}}}\n", indent);
+            }
+            Stmt::Loop(stmts) => {
+                *out += &format!("{}loop {{\n", indent);
+                body(stmts, out);
+                *out += &format!("{}}}\n", indent);
+            }
+            Stmt::DoWhile { body: stmts, cond } => {
+                *out += &format!("{}do {{\n", indent);
+                body(stmts, out);
+                *out += &format!("{}}} while {}\n", indent, cond);
+            }
+            Stmt::Break => *out += &format!("{}break\n", indent),
+            Stmt::Continue => *out += &format!("{}continue\n", indent),
+            Stmt::Goto(target) => *out += &format!("{}goto L{}\n", indent, target),
+            Stmt::Return => *out += &format!("{}return\n", indent),
+            Stmt::Halt => *out += &format!("{}halt\n", indent),
+            Stmt::NotCode(address) => {
+                *out += &format!("{}// runs into {}, which is not code\n", indent, address)
+            }
+        }
+    }
+}
+
+fn goto_targets(stmts: &[Stmt], targets: &mut BTreeSet<usize>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Goto(target) => {
+                targets.insert(*target);
+            }
+            Stmt::If {
+                then, otherwise, ..
+            } => {
+                goto_targets(then, targets);
+                goto_targets(otherwise, targets);
+            }
+            Stmt::While { body, .. } | Stmt::Loop(body) | Stmt::DoWhile { body, .. } => {
+                goto_targets(body, targets)
+            }
+            _ => {}
+        }
+    }
+}
+
+// Turn a program into pseudo-code with while and do-while loops, if/else
+// and early exits where the jumps allow it, and gotos where they do not.
+// A block that stores its own end address and then jumps is a call.
+// Functions start where calls to fixed addresses go, and at code that nothing jumps
+// to but that may be called through a pointer; jumping through rb[...] in
+// a function returns. Memory cells used at fixed addresses are variables,
+// declared with their initial values. Code that patches its own operands
+// is shown as it is before running.
+pub fn decompile(program: &[i64]) -> String {
+    let cfg = ControlFlowGraph::new(program);
+    let calls: BTreeMap<usize, Target> = cfg
+        .blocks()
+        .filter_map(|block| match block.exit {
+            Exit::Goto(callee) => {
+                let end = (block.end() as i64, Mode::Immediate);
+                let stores_end = block
+                    .ops
+                    .iter()
+                    .any(|(_, op)| op.write_param().is_some() && op.params.contains(&end));
+                Some((block.start, callee)).filter(|_| stores_end)
+            }
+            _ => None,
+        })
+        .collect();
+    // Functions start at 0, where a call goes, and at code that nothing
+    // jumps to but that is not where a call returns to either, such as a
+    // function passed to another one
+    let returns: BTreeSet<usize> = calls
+        .keys()
+        .filter_map(|&start| cfg.block(start).map(Block::end))
+        .collect();
+    let mut entries: BTreeSet<usize> = cfg
+        .blocks()
+        .map(|block| block.start)
+        .filter(|&start| cfg.predecessors(start).is_empty() && !returns.contains(&start))
+        .collect();
+    entries.extend(calls.values().filter_map(|&callee| match callee {
+        Target::Static(address) => Some(address),
+        Target::Indirect(..) => None,
+    }));
+    entries.insert(0);
+
+    // Cells read by any instruction, except by a jump that tests the
+    // comparison right before it. Only a comparison that stores to a cell
+    // that is not read otherwise can be folded into its jump.
+    let mut reads = Vec::new();
+    for block in cfg.blocks() {
+        let mut previous: Option<&Op> = None;
+        for (_, op) in &block.ops {
+            for (idx, &param) in op.params.iter().enumerate() {
+                let tested = (op.opcode == 5 || op.opcode == 6)
+                    && idx == 0
+                    && previous.is_some_and(|prev| is_compare(prev) && prev.params[2] == param);
+                if param.1 != Mode::Immediate
+                    && Some(idx) != op.write_param()
+                    && !tested
+                    && !reads.contains(&param)
+                {
+                    reads.push(param);
+                }
+            }
+            previous = Some(op);
+        }
+    }
+
+    let mut variables = BTreeSet::new();
+    for (_, op) in cfg.blocks().flat_map(|block| &block.ops) {
+        for &(operand, mode) in &op.params {
+            if mode == Mode::Position && operand >= 0 {
+                variables.insert(operand as usize);
+            }
+        }
+    }
+    let mut result = String::new();
+    for &address in &variables {
+        let value = program.get(address).copied().unwrap_or(0);
+        result += &format!("var v{} = {}\n", address, value);
+    }
+
+    for &entry in entries.iter().filter(|&&entry| cfg.block(entry).is_some()) {
+        let mut function = Function::new(&cfg, &calls, &reads, entry, entry == 0);
+        let mut stmts = Vec::new();
+        function.region(entry, None, &mut stmts);
+        // Code only reached by gotos goes after the rest
+        loop {
+            let mut targets = BTreeSet::new();
+            goto_targets(&stmts, &mut targets);
+            match targets.difference(&function.emitted).next() {
+                Some(&target) => function.region(target, None, &mut stmts),
+                None => break,
+            }
+        }
+        let mut labels = BTreeSet::new();
+        goto_targets(&stmts, &mut labels);
+        let name = if entry == 0 {
+            "main".to_owned()
+        } else {
+            format!("f{}", entry)
+        };
+        result += &format!("\nfn {}() {{\n", name);
+        render(&stmts, 1, &labels, &mut result);
+        result += "}\n";
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::Program;
+
+    #[test]
+    fn test_decompile() {
+        // Output the input counting down to 1
+        let countdown = [
+            3, 100, 1006, 100, 14, 4, 100, 1001, 100, -1, 100, 1105, 1, 2, 99,
+        ];
+        assert_eq!(
+            decompile(&countdown),
+            "var v100 = 0\n\n\
+             fn main() {\n    \
+                 v100 := input()\n    \
+                 while v100 != 0 {\n        \
+                     output(v100)\n        \
+                     v100 := v100 + -1\n    \
+                 }\n    \
+                 halt\n\
+             }\n"
+        );
+
+        // A call to a function that echoes input until it reads 0
+        let call = [
+            109, 100, 21101, 9, 0, 0, 1105, 1, 11, 99, 0, 3, 10, 1006, 10, 21, 4, 10, 1105, 1, 11,
+            2105, 1, 0,
+        ];
+        assert_eq!(
+            decompile(&call),
+            "var v10 = 0\n\n\
+             fn main() {\n    \
+                 rb += 100\n    \
+                 call f11\n    \
+                 halt\n\
+             }\n\n\
+             fn f11() {\n    \
+                 loop {\n        \
+                     v10 := input()\n        \
+                     if v10 == 0 { break }\n        \
+                     output(v10)\n    \
+                 }\n    \
+                 return\n\
+             }\n"
+        );
+
+        // if/else, with the comparison in the condition
+        let choice = [
+            3, 20, 1007, 20, 5, 21, 1005, 21, 16, 104, 1, 1105, 1, 18, 0, 0, 104, 2, 99,
+        ];
+        assert_eq!(
+            decompile(&choice),
+            "var v20 = 0\nvar v21 = 0\n\n\
+             fn main() {\n    \
+                 v20 := input()\n    \
+                 if v20 >= 5 {\n        \
+                     output(1)\n    \
+                 } else {\n        \
+                     output(2)\n    \
+                 }\n    \
+                 halt\n\
+             }\n"
+        );
+
+        // Branches that run off the end, or into data that reads as a
+        // jump and then runs off the end
+        assert_eq!(
+            decompile(&[3, 0, 1005, 0, 0]),
+            "var v0 = 3\n\n\
+             fn main() {\n    \
+                 loop {\n        \
+                     v0 := input()\n        \
+                     if v0 == 0 {\n            \
+                         // runs into 5, which is not code\n        \
+                     }\n    \
+                 }\n\
+             }\n"
+        );
+        assert_eq!(
+            decompile(&[3, 0, 1005, 0, 6, 99, 5]),
+            "var v0 = 3\n\n\
+             fn main() {\n    \
+                 v0 := input()\n    \
+                 if v0 == 0 {\n        \
+                     halt\n    \
+                 }\n    \
+                 if v0 != 0 {\n        \
+                     goto v0\n    \
+                 }\n    \
+                 // runs into 9, which is not code\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_do_while() {
+        // Output the input counting down to 1, at least once
+        let countdown = [3, 100, 4, 100, 1001, 100, -1, 100, 1005, 100, 2, 99];
+        assert_eq!(
+            decompile(&countdown),
+            "var v100 = 0\n\n\
+             fn main() {\n    \
+                 v100 := input()\n    \
+                 do {\n        \
+                     output(v100)\n        \
+                     v100 := v100 + -1\n    \
+                 } while v100 != 0\n    \
+                 halt\n\
+             }\n"
+        );
+
+        // A comparison whose result is read again keeps its store
+        let compare = [3, 20, 1007, 20, 5, 21, 1005, 21, 10, 99, 4, 21, 99];
+        assert!(decompile(&compare).contains("    v21 := v20 < 5\n    if v20 >= 5 {"));
+
+        // An arm with nothing to show leaves out the else
+        let stmts = [Stmt::If {
+            cond: "v1 != 0".to_owned(),
+            then: vec![Stmt::Line("output(1)".to_owned())],
+            otherwise: vec![Stmt::Block(5)],
+        }];
+        let mut text = String::new();
+        render(&stmts, 0, &BTreeSet::new(), &mut text);
+        assert_eq!(text, "if v1 != 0 {\n    output(1)\n}\n");
+    }
+
+    #[test]
+    fn test_puzzle_programs() {
+        // Every program decompiles with few gotos
+        for day in &[13, 15, 17, 25] {
+            let path = format!("{}/input/2019/day{}.txt", env!("CARGO_MANIFEST_DIR"), day);
+            let program = Program::load(path).unwrap();
+            let text = decompile(&program);
+            let gotos = text.matches("goto").count();
+            let lines = text.lines().count();
+            assert!(
+                gotos * 10 < lines,
+                "day {}: {} gotos in {} lines",
+                day,
+                gotos,
+                lines
+            );
+            assert!(text.contains("fn main() {"));
+        }
+    }
+}
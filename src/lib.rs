@@ -4,8 +4,11 @@ extern crate pathfinding;
 
 use aoc_runner_derive::aoc_lib;
 
-mod intcode;
-mod intcode_asm;
+pub mod intcode;
+pub mod intcode_aot;
+pub mod intcode_asm;
+pub mod intcode_cfg;
+pub mod intcode_decompile;
 
 mod day01;
 mod day02;
@@ -0,0 +1,25 @@
+// Translate an Intcode program into a Rust module, for programs that are
+// run so often that interpreting them is too slow.
+//
+// Usage: intcode-transpile <program file> [module name] > program.rs
+// The module is called `program` by default. See intcode_aot::transpile
+// for what it contains.
+
+use advent_of_code_2019::intcode::Program;
+use advent_of_code_2019::intcode_aot::transpile;
+use std::env;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("Usage: {} <program file> [module name]", args[0]);
+        process::exit(2);
+    }
+    let program = Program::load(&args[1]).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args[1], e);
+        process::exit(1);
+    });
+    let name = args.get(2).map_or("program", String::as_str);
+    print!("{}", transpile(&program, name));
+}